@@ -1,7 +1,7 @@
 mod parse;
 
 use tora_db::engine::Db;
-use tora_db::{Data, Type};
+use tora_db::Type;
 
 fn main() -> Result<(), String> {
     let mut db = Db::default();
@@ -9,12 +9,13 @@ fn main() -> Result<(), String> {
     db.append_column("Name".to_string(), Type::String)
         .map_err(|e| e.to_string())?;
 
-    db.append_row(vec![Data::String("John".to_string())])
-        .map_err(|e| e.to_string())?;
+    let instruction =
+        parse::parse_append_row_for("APPEND_ROW `John`str", &db).map_err(|e| e.to_string())?;
+    db.query(instruction).map_err(|e| e.to_string())?;
 
     tora::write_to_file("test.tdb", &db).map_err(|e| e.to_string())?;
 
-    let mut db: Db = tora::read_from_file("test.tdb").map_err(|e| e.to_string())?;
+    let db: Db = tora::read_from_file("test.tdb").map_err(|e| e.to_string())?;
     println!("{}", db.fetch_value(0, 0).map_err(|e| e.to_string())?);
     Ok(())
 }
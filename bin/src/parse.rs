@@ -1 +1,181 @@
-// TODO implement parsing
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+
+use tora_db::engine::Db;
+use tora_db::{Data, Instruction};
+
+/// An error produced when parsing fails, pointing at the offending part of the input.
+#[derive(Debug)]
+pub struct ParseError {
+    span: Range<usize>,
+    message: String,
+}
+
+impl ParseError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.message, self.span.start, self.span.end)
+    }
+}
+
+/// Parses an `APPEND_ROW` command followed by whitespace-separated [Data] literals.
+///
+/// Literals use the same format as [Data]'s display, e.g. `42int`, `3.14double`,
+/// `` `hello`str `` or `NULL`.
+pub fn parse_append_row(input: &str) -> Result<Instruction, ParseError> {
+    let mut pos = skip_whitespace(input, 0);
+    let keyword = "APPEND_ROW";
+
+    if !input[pos..].starts_with(keyword) {
+        let end = next_whitespace(input, pos);
+        return Err(ParseError::new(pos..end, "expected `APPEND_ROW`"));
+    }
+    pos += keyword.len();
+
+    let mut row = Vec::new();
+
+    loop {
+        let start = skip_whitespace(input, pos);
+
+        if start == input.len() {
+            return Ok(Instruction::AppendRow(row));
+        }
+        if start == pos {
+            let end = next_whitespace(input, start);
+            return Err(ParseError::new(start..end, "expected whitespace"));
+        }
+
+        let end = literal_end(input, start)?;
+        row.push(parse_data(&input[start..end], start)?);
+        pos = end;
+    }
+}
+
+/// Parses an `APPEND_ROW` command like [parse_append_row], checking that it holds one value per
+/// column of the database.
+///
+/// This reports a wrong amount of values before the instruction reaches [Db::query].
+pub fn parse_append_row_for(input: &str, db: &Db) -> Result<Instruction, ParseError> {
+    let instruction = parse_append_row(input)?;
+
+    if let Instruction::AppendRow(row) = &instruction {
+        if row.len() != db.expected_row_len() {
+            let start = input.find("APPEND_ROW").map_or(0, |i| i + "APPEND_ROW".len());
+            let span = start..input.trim_end().len();
+
+            let message = format!("expected {} values, found {}", db.expected_row_len(), row.len());
+            return Err(ParseError::new(span, message));
+        }
+    }
+    Ok(instruction)
+}
+
+/// Parses a single [Data] literal, with `offset` being its position within the whole input.
+fn parse_data(literal: &str, offset: usize) -> Result<Data, ParseError> {
+    let span = offset..offset + literal.len();
+
+    if literal == "NULL" {
+        return Ok(Data::Null);
+    }
+    if let Some(inner) = literal.strip_prefix('`').and_then(|s| s.strip_suffix("`str")) {
+        return Ok(Data::String(inner.to_string()));
+    }
+
+    let invalid = |suffix: &str| ParseError::new(span.clone(), format!("invalid {suffix} literal"));
+
+    if let Some(value) = literal.strip_suffix("int") {
+        value.parse().map(Data::Int).map_err(|_| invalid("int"))
+    } else if let Some(value) = literal.strip_suffix("long") {
+        value.parse().map(Data::Long).map_err(|_| invalid("long"))
+    } else if let Some(value) = literal.strip_suffix("float") {
+        value.parse().map(Data::Float).map_err(|_| invalid("float"))
+    } else if let Some(value) = literal.strip_suffix("double") {
+        value.parse().map(Data::Double).map_err(|_| invalid("double"))
+    } else {
+        Err(ParseError::new(span, "missing or unknown type suffix"))
+    }
+}
+
+/// Finds the end of the literal starting at `start`, which may contain whitespace if quoted.
+fn literal_end(input: &str, start: usize) -> Result<usize, ParseError> {
+    if !input[start..].starts_with('`') {
+        return Ok(next_whitespace(input, start));
+    }
+
+    match input[start + 1..].find('`') {
+        Some(close) => Ok(next_whitespace(input, start + 1 + close)),
+        None => Err(ParseError::new(start..input.len(), "unterminated string literal")),
+    }
+}
+
+fn skip_whitespace(input: &str, pos: usize) -> usize {
+    input[pos..]
+        .find(|c: char| !c.is_whitespace())
+        .map_or(input.len(), |i| pos + i)
+}
+
+fn next_whitespace(input: &str, pos: usize) -> usize {
+    input[pos..]
+        .find(char::is_whitespace)
+        .map_or(input.len(), |i| pos + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use tora_db::Type;
+
+    use super::*;
+
+    #[test]
+    fn append_row_with_wrong_length_is_rejected_before_query() {
+        let mut db = Db::default();
+        db.append_column("Name".to_string(), Type::String).unwrap();
+        db.append_column("Age".to_string(), Type::Int).unwrap();
+
+        let err = parse_append_row_for("APPEND_ROW `John`str", &db).unwrap_err();
+        assert_eq!(err.span, 10..20);
+        assert_eq!(err.message, "expected 2 values, found 1");
+
+        let instruction = parse_append_row_for("APPEND_ROW `John`str 42int", &db).unwrap();
+        assert!(db.query(instruction).is_ok());
+    }
+
+    #[test]
+    fn append_row_parses_every_literal() {
+        let input = "APPEND_ROW 42int -7long 1.5float 2.25double `a b`str NULL";
+        let Instruction::AppendRow(row) = parse_append_row(input).unwrap() else {
+            panic!("expected an APPEND_ROW instruction");
+        };
+        assert_eq!(row, [
+            Data::Int(42),
+            Data::Long(-7),
+            Data::Float(1.5),
+            Data::Double(2.25),
+            Data::from("a b"),
+            Data::Null,
+        ]);
+    }
+
+    #[test]
+    fn malformed_input_reports_its_span() {
+        let cases = [
+            ("APPEND_ROW 1int 2bool", 16..21, "missing or unknown type suffix"),
+            ("APPEND_ROW xint", 11..15, "invalid int literal"),
+            ("APPEND_ROW `open", 11..16, "unterminated string literal"),
+            ("INSERT 1int", 0..6, "expected `APPEND_ROW`"),
+            ("APPEND_ROW1int", 10..14, "expected whitespace"),
+        ];
+        for (input, span, message) in cases {
+            let err = parse_append_row(input).unwrap_err();
+            assert_eq!((err.span, err.message.as_str()), (span, message), "{input}");
+        }
+    }
+}
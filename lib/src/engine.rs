@@ -1,11 +1,18 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
 
 use derive_more::{Display, Error};
-use tora::read::ToraRead;
-use tora::{ReadStruct, WriteStruct};
+use tora::read::{FromReader, ToraRead};
+use tora::write::{SerializeIo, ToraWrite};
 
-use crate::{Column, Data, Id, Index, Instruction, Type};
+use crate::{Column, Comparison, Data, Id, Index, Instruction, Type};
 
 /// A row of data.
 pub type Row = Vec<Data>;
@@ -13,6 +20,9 @@ pub type Row = Vec<Data>;
 /// A [Result] containing a [QueryResponse] on success, and a [QueryError] on error.
 pub type QueryResult = Result<QueryResponse, QueryError>;
 
+/// A callback notified of changes to a database.
+pub type Observer = Box<dyn FnMut(&ChangeEvent) + Send>;
+
 /// A failure response from the database.
 #[derive(Display, Debug, Error)]
 pub enum QueryError {
@@ -32,8 +42,117 @@ pub enum QueryError {
     #[display("Data does not fit restrictions")]
     DataMismatch,
 
+    /// The data did not match the type restriction of its column.
+    ///
+    /// Contains the expected type, followed by the received type.
     #[display("Type mismatch: {_0}, {_1}")]
     TypeMismatch(Type, Type),
+
+    /// The operation would exceed the maximum amount of rows or columns.
+    #[display("Limit exceeded")]
+    LimitExceeded,
+
+    /// A string value was longer than the maximum length of its column.
+    #[display("Length exceeded")]
+    LengthExceeded,
+
+    /// A column with the same name already exists.
+    #[display("Duplicate name: `{_0}`")]
+    DuplicateName(#[error(not(source))] String),
+
+    /// A column name was empty or only contained whitespace.
+    #[display("Invalid name")]
+    InvalidName,
+
+    /// Attempted to modify a read-only database.
+    #[display("Database is read-only")]
+    ReadOnly,
+
+    /// Attempted to store a `NULL` value in a column which does not accept it.
+    #[display("Null constraint violation")]
+    NullConstraintViolation,
+
+    /// Attempted to store a primary key value which is already used by another row.
+    #[display("Duplicate key")]
+    DuplicateKey,
+
+    /// Attempted to insert a row identical to an existing row, while duplicate rows are rejected.
+    #[display("Duplicate row")]
+    DuplicateRow,
+
+    /// Attempted to store a `NaN` or infinite value, while non-finite values are rejected.
+    #[display("Non-finite value")]
+    NonFinite,
+
+    /// A batch operation referred to the same column more than once.
+    ///
+    /// Contains the index of the repeated column.
+    #[display("Repeated column: {_0}")]
+    RepeatedColumn(#[error(not(source))] Index),
+
+    /// Attempted to undo an operation after the database was changed again.
+    #[display("Stale undo token")]
+    StaleUndo,
+
+    /// An item of a batch operation failed.
+    ///
+    /// Contains the position of the failed item within the batch, followed by its error.
+    #[display("Batch failed at {_0}: {_1}")]
+    BatchFailed(Index, Box<QueryError>),
+}
+
+impl QueryError {
+    /// Returns a stable numeric code identifying this error, suitable for protocols and exit
+    /// codes.
+    ///
+    /// Codes are never reused or changed once assigned.
+    pub const fn code(&self) -> u16 {
+        match self {
+            Self::IndexOutOfBounds => 1,
+            Self::DataOutOfBounds => 2,
+            Self::NotFound => 3,
+            Self::DataMismatch => 4,
+            Self::TypeMismatch(..) => 5,
+            Self::LimitExceeded => 6,
+            Self::BatchFailed(..) => 7,
+            Self::LengthExceeded => 8,
+            Self::DuplicateName(_) => 9,
+            Self::InvalidName => 10,
+            Self::ReadOnly => 11,
+            Self::NullConstraintViolation => 12,
+            Self::DuplicateKey => 13,
+            Self::DuplicateRow => 14,
+            Self::NonFinite => 15,
+            Self::RepeatedColumn(_) => 16,
+            Self::StaleUndo => 17,
+        }
+    }
+
+    /// Returns whether the error was caused by the request itself, such as an out-of-bounds index
+    /// or a value violating a constraint, rather than by the state of the database.
+    ///
+    /// Retrying a client error unchanged will fail again, while [QueryError::LimitExceeded] and
+    /// [QueryError::ReadOnly] may succeed once the database changes.
+    pub fn is_client_error(&self) -> bool {
+        match self {
+            Self::IndexOutOfBounds
+            | Self::DataOutOfBounds
+            | Self::NotFound
+            | Self::DataMismatch
+            | Self::TypeMismatch(..)
+            | Self::LengthExceeded
+            | Self::DuplicateName(_)
+            | Self::InvalidName
+            | Self::NullConstraintViolation
+            | Self::DuplicateKey
+            | Self::DuplicateRow
+            | Self::NonFinite
+            | Self::RepeatedColumn(_)
+            | Self::StaleUndo => true,
+            Self::LimitExceeded | Self::ReadOnly => false,
+            Self::BatchFailed(_, e) => e.is_client_error(),
+        }
+    }
 }
 
 /// A success response from the database.
@@ -55,15 +174,280 @@ pub enum QueryResponse {
     /// A single value returned from `FETCH_VALUE`.
     #[display("Returned single value: {_0}")]
     OkSingle(Data),
+
+    /// Multiple rows returned from a successful read operation.
+    #[display("Returned {} rows", _0.len())]
+    Rows(Vec<Row>),
+}
+
+impl QueryResponse {
+    /// Returns the value if this is an [QueryResponse::OkSingle].
+    pub const fn as_single(&self) -> Option<&Data> {
+        match self {
+            Self::OkSingle(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns the rows if this is a [QueryResponse::Rows].
+    pub fn as_rows(&self) -> Option<&[Row]> {
+        match self {
+            Self::Rows(rows) => Some(rows),
+            _ => None,
+        }
+    }
+}
+
+/// A change to a database, passed to the observers registered with [Db::on_change].
+///
+/// When multiple rows or columns are deleted at once, an event is produced for each in descending
+/// order, so every index is valid at the time of its event.
+///
+/// Changes to column settings such as defaults, metadata, indexes and the primary key do not
+/// produce events, as they leave the stored values untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeEvent {
+    /// A row was inserted at the given index.
+    RowInserted(Index),
+
+    /// The row at the given index was deleted.
+    RowDeleted(Index),
+
+    /// The value at the given row and column index was updated.
+    CellUpdated(Index, Index),
+
+    /// A column was added at the given index.
+    ColumnAdded(Index),
+
+    /// The column at the given index was deleted.
+    ColumnDropped(Index),
+
+    /// The column at the given index was renamed.
+    ColumnRenamed(Index),
+
+    /// The columns at the given indices swapped positions, along with their values.
+    ColumnsSwapped(Index, Index),
+
+    /// Every row may have moved to a new index, as by [Db::sort_by_columns].
+    RowsReordered,
+}
+
+/// A report produced by [Db::try_cast_column].
+#[derive(Debug, Default)]
+pub struct CastReport {
+    converted: Vec<Index>,
+    failed: Vec<(Index, Data)>,
+}
+
+impl CastReport {
+    /// The indices of the rows whose values were successfully converted.
+    pub fn converted(&self) -> &[Index] {
+        &self.converted
+    }
+
+    /// The indices of the rows whose values could not be converted, along with the original value.
+    pub fn failed(&self) -> &[(Index, Data)] {
+        &self.failed
+    }
+
+    /// Returns true if every value was converted, meaning the column type was changed.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// The differences between two schemas, produced by [Db::compare_schema].
+///
+/// Columns are matched by name.
+#[derive(Debug, Default)]
+pub struct SchemaDiff {
+    only_in_self: Vec<String>,
+    only_in_other: Vec<String>,
+    type_changed: Vec<(String, Type, Type)>,
+}
+
+impl SchemaDiff {
+    /// The names of the columns only present in the compared database.
+    pub fn only_in_self(&self) -> &[String] {
+        &self.only_in_self
+    }
+
+    /// The names of the columns only present in the other database.
+    pub fn only_in_other(&self) -> &[String] {
+        &self.only_in_other
+    }
+
+    /// The columns present in both databases with differing types.
+    ///
+    /// Contains the name, followed by the type in the compared database and the other database.
+    pub fn type_changed(&self) -> &[(String, Type, Type)] {
+        &self.type_changed
+    }
+
+    /// Returns true if there are no differences.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.type_changed.is_empty()
+    }
+}
+
+/// Options controlling how values are rendered by [Db::render_table].
+#[derive(Debug, Clone)]
+pub struct DisplayOptions {
+    float_precision: Option<usize>,
+    null_text: String,
+}
+
+impl DisplayOptions {
+    /// Renders floating point values with the given amount of decimal places, instead of their
+    /// full precision.
+    pub fn with_float_precision(mut self, precision: usize) -> Self {
+        self.float_precision = Some(precision);
+        self
+    }
+
+    /// Renders `NULL` values as the given text.
+    pub fn with_null_text(mut self, text: String) -> Self {
+        self.null_text = text;
+        self
+    }
+
+    /// Renders the value without any type annotations.
+    pub fn render(&self, data: &Data) -> String {
+        match (data, self.float_precision) {
+            (Data::Float(v), Some(p)) => format!("{v:.p$}"),
+            (Data::Double(v), Some(p)) => format!("{v:.p$}"),
+            (Data::Null, _) => self.null_text.clone(),
+            _ => data.to_plain_string(),
+        }
+    }
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            float_precision: None,
+            null_text: "NULL".to_string(),
+        }
+    }
+}
+
+/// A cursor walking through the rows of a database page by page, created by [Db::cursor].
+#[derive(Debug, Clone)]
+pub struct RowCursor<'a> {
+    rows: &'a [Row],
+    page_size: usize,
+    position: usize,
+}
+
+impl RowCursor<'_> {
+    /// Returns the next page of rows, or [None] once every row has been returned.
+    ///
+    /// The last page may contain fewer rows than the page size.
+    pub fn next_page(&mut self) -> Option<Vec<Row>> {
+        if !self.has_next() {
+            return None;
+        }
+        let end = self.rows.len().min(self.position + self.page_size);
+        let page = self.rows[self.position..end].to_vec();
+        self.position = end;
+        Some(page)
+    }
+
+    /// Returns true if there are rows left to return.
+    pub fn has_next(&self) -> bool {
+        self.position < self.rows.len()
+    }
+}
+
+/// A record of a column operation, which can be reverted by passing it to [Db::undo].
+///
+/// A token is only valid until the database is changed again.
+#[derive(Debug)]
+pub struct UndoToken {
+    op: UndoOp,
+    changes: u64,
+}
+
+/// The state needed to revert a column operation.
+#[derive(Debug)]
+enum UndoOp {
+    Dropped {
+        index: Index,
+        column: Column,
+        values: Vec<Data>,
+        primary_key: bool,
+        indexed: bool,
+    },
+    Renamed {
+        index: Index,
+        old_name: String,
+    },
+}
+
+/// A hash index over the values of a single column.
+///
+/// Values are bucketed by their hash, so lookups must still compare the stored values.
+struct HashIndex {
+    column: Index,
+    buckets: HashMap<u64, Vec<Index>>,
+}
+
+impl HashIndex {
+    /// Builds an index over the given column of the rows.
+    fn build(column: Index, rows: &[Row]) -> Self {
+        let mut index = Self {
+            column,
+            buckets: HashMap::new(),
+        };
+
+        for (i, row) in rows.iter().enumerate() {
+            index.insert(&row[column as usize], i as Index);
+        }
+        index
+    }
+
+    /// Records that the row at the given index holds the value.
+    fn insert(&mut self, value: &Data, row: Index) {
+        self.buckets.entry(hash_data(value)).or_default().push(row);
+    }
+
+    /// Moves every recorded row at or after the given index down by one.
+    fn shift_from(&mut self, row: Index) {
+        for rows in self.buckets.values_mut() {
+            for i in rows.iter_mut().filter(|i| **i >= row) {
+                *i += 1;
+            }
+        }
+    }
+
+    /// Returns the indices of the rows which may hold the value.
+    fn candidates(&self, value: &Data) -> &[Index] {
+        self.buckets.get(&hash_data(value)).map_or(&[], Vec::as_slice)
+    }
 }
 
 /// A simple database.
 /// 
 /// All data is stored in the rows, while the columns are for type checking and data validation.
-#[derive(Default, ReadStruct, WriteStruct)]
+///
+/// Indexes and settings such as read-only mode are not persisted, and must be recreated after
+/// loading. Numeric values and lengths are always serialized in little-endian byte order, so files
+/// can be shared between platforms.
+#[derive(Default)]
 pub struct Db {
     columns: Vec<Column>,
     rows: Vec<Row>,
+    primary_key: Option<Index>,
+    indexes: Vec<HashIndex>,
+    read_only: bool,
+    autopad: bool,
+    reject_duplicate_rows: bool,
+    coerce_on_insert: bool,
+    validate_on_write: bool,
+    auto_index: bool,
+    reject_non_finite: bool,
+    observers: Vec<Observer>,
+    changes: u64,
 }
 
 impl Db {
@@ -71,156 +455,4802 @@ impl Db {
     ///
     /// Always returns [Ok] with some value.
     pub fn delete_column_by_name(&mut self, name: &str) -> QueryResult {
-        for (i, col) in self.columns.iter().enumerate() {
-            if col.name == name {
-                self.columns.remove(i);
-                
-                for row in &mut self.rows {
-                    row.remove(i);
-                }
-                return Ok(QueryResponse::Ok(i as Index));
-            }
+        self.check_writable()?;
+        match self.columns.iter().position(|col| col.name == name) {
+            Some(i) => self.delete_column_by_index(i as Index),
+            None => Err(QueryError::NotFound),
         }
-        Err(QueryError::NotFound)
     }
 
     /// Deletes a column by its index.
     ///
     /// Returns an [Err] if the index is greater than or equal to the amount of columns.
     pub fn delete_column_by_index(&mut self, index: Index) -> QueryResult {
-        if (index as usize) < self.columns.len() {
-            self.columns.remove(index as usize);
+        self.check_writable()?;
+        self.check_column(index)?;
+        self.remove_column(index);
+        Ok(QueryResponse::Ok(index))
+    }
 
-            for row in &mut self.rows {
-                row.remove(index as usize);
+    /// Deletes the given column, returning its definition along with the value it held in each
+    /// row.
+    ///
+    /// Its name, type and values may be passed to [Db::add_column_with_values] to move the column to
+    /// another database.
+    pub fn extract_column(&mut self, id: Id) -> Result<(Column, Vec<Data>), QueryError> {
+        self.check_writable()?;
+        let index = self.resolve_column(&id)?;
+        Ok(self.remove_column(index))
+    }
+
+    /// Deletes every column matching the predicate, along with its values, in a single pass.
+    ///
+    /// Returns the original indices of the deleted columns.
+    pub fn prune_columns<F>(&mut self, pred: F) -> QueryResult
+    where F: Fn(&Column) -> bool {
+        self.check_writable()?;
+        let keep: Vec<bool> = self.columns.iter().map(|col| !pred(col)).collect();
+
+        let mut remap = Vec::with_capacity(keep.len());
+        let mut removed = Vec::new();
+
+        for (i, &kept) in keep.iter().enumerate() {
+            if kept {
+                remap.push(Some((i - removed.len()) as Index));
+            } else {
+                remap.push(None);
+                removed.push(i as Index);
             }
-            return Ok(QueryResponse::Ok(index));
         }
-        Err(QueryError::IndexOutOfBounds)
+        if removed.is_empty() {
+            return Ok(QueryResponse::ModifiedColumns(Vec::new()));
+        }
+
+        let mut flags = keep.iter();
+        self.columns.retain(|_| *flags.next().unwrap());
+
+        for row in &mut self.rows {
+            let mut flags = keep.iter();
+            row.retain(|_| *flags.next().unwrap());
+        }
+        self.indexes.retain(|idx| keep[idx.column as usize]);
+
+        for idx in &mut self.indexes {
+            idx.column = remap[idx.column as usize].unwrap();
+        }
+        self.primary_key = self.primary_key.and_then(|pk| remap[pk as usize]);
+
+        for &index in removed.iter().rev() {
+            self.emit(ChangeEvent::ColumnDropped(index));
+        }
+        Ok(QueryResponse::ModifiedColumns(removed.into_iter().map(Id::Index).collect()))
+    }
+
+    /// Deletes the column at the given index like [Db::delete_column_by_index], returning a token
+    /// which restores it along with its values.
+    pub fn delete_column_undoable(&mut self, index: Index) -> Result<UndoToken, QueryError> {
+        self.check_writable()?;
+        self.check_column(index)?;
+
+        let primary_key = self.primary_key == Some(index);
+        let indexed = self.has_index(index);
+        let (column, values) = self.remove_column(index);
+
+        let op = UndoOp::Dropped {
+            index,
+            column,
+            values,
+            primary_key,
+            indexed,
+        };
+        Ok(self.undo_token(op))
+    }
+
+    /// Renames the column at the given index, returning a token which restores its previous name.
+    ///
+    /// Returns an [Err] if the name is invalid or used by another column.
+    pub fn rename_column_undoable(
+        &mut self,
+        index: Index,
+        name: String,
+    ) -> Result<UndoToken, QueryError> {
+        let col = self.check_column(index)?;
+        let old_name = self.columns[col].name.clone();
+        self.rename_columns(vec![(index, name)])?;
+        Ok(self.undo_token(UndoOp::Renamed { index, old_name }))
+    }
+
+    /// Reverts the column operation recorded by the token.
+    ///
+    /// A dropped column is inserted back at its original position with its values, index and
+    /// primary key status. Returns [QueryError::StaleUndo] if any row or column was changed since
+    /// the token was created, as its values or index may no longer line up.
+    pub fn undo(&mut self, token: UndoToken) -> QueryResult {
+        self.check_writable()?;
+        if token.changes != self.changes {
+            return Err(QueryError::StaleUndo);
+        }
+        match token.op {
+            UndoOp::Dropped {
+                index,
+                column,
+                values,
+                primary_key,
+                indexed,
+            } => {
+                if index as usize > self.columns.len() {
+                    return Err(QueryError::IndexOutOfBounds);
+                }
+                if values.len() != self.rows.len() {
+                    return Err(QueryError::DataMismatch);
+                }
+                if self.columns.iter().any(|col| col.name == column.name) {
+                    return Err(QueryError::DuplicateName(column.name));
+                }
+                self.columns.insert(index as usize, column);
+
+                for (row, val) in self.rows.iter_mut().zip(values) {
+                    row.insert(index as usize, val);
+                }
+                for idx in &mut self.indexes {
+                    if idx.column >= index {
+                        idx.column += 1;
+                    }
+                }
+                self.primary_key = match self.primary_key {
+                    Some(pk) if pk >= index => Some(pk + 1),
+                    None if primary_key => Some(index),
+                    pk => pk,
+                };
+                if indexed {
+                    self.indexes.push(HashIndex::build(index, &self.rows));
+                }
+                self.emit(ChangeEvent::ColumnAdded(index));
+                Ok(QueryResponse::Ok(index))
+            }
+            UndoOp::Renamed { index, old_name } => self.rename_columns(vec![(index, old_name)]),
+        }
     }
 
     /// Deletes a row by its index.
     pub fn delete_row_by_index(&mut self, index: Index) -> QueryResult {
+        self.check_writable()?;
         if (index as usize) < self.rows.len() {
             self.rows.remove(index as usize);
+            self.rebuild_indexes();
+            self.emit(ChangeEvent::RowDeleted(index));
             return Ok(QueryResponse::Ok(index));
         }
         Err(QueryError::IndexOutOfBounds)
     }
 
-    /// Creates and appends a new column with the given name and type restriction.
-    /// 
-    /// A `NULL` value will be appended to all rows.
-    pub fn append_column(&mut self, name: String, ty_restrict: Type) -> QueryResult {
-        self.append_column_default(name, ty_restrict, Data::Null)
+    /// Swaps the positions of two columns, along with their values in every row.
+    pub fn swap_columns(&mut self, a: Index, b: Index) -> QueryResult {
+        self.check_writable()?;
+        let (x, y) = (self.check_column(a)?, self.check_column(b)?);
+        self.columns.swap(x, y);
+
+        for row in &mut self.rows {
+            row.swap(x, y);
+        }
+        for idx in &mut self.indexes {
+            if idx.column == a {
+                idx.column = b;
+            } else if idx.column == b {
+                idx.column = a;
+            }
+        }
+        if self.primary_key == Some(a) {
+            self.primary_key = Some(b);
+        } else if self.primary_key == Some(b) {
+            self.primary_key = Some(a);
+        }
+        self.emit(ChangeEvent::ColumnsSwapped(a, b));
+        Ok(QueryResponse::ModifiedColumns(vec![Id::Index(a), Id::Index(b)]))
     }
 
-    /// Creates and appends a new column with the given name and type restriction.
+    /// Removes and returns every row, leaving the columns intact.
     ///
-    /// The provided default value will be appended to all rows.
-    pub fn append_column_default(&mut self, name: String, ty_restrict: Type, default: Data) -> QueryResult {
-        self.columns.push(Column::new(name, ty_restrict));
+    /// Indexes are kept, but emptied.
+    pub fn take_rows(&mut self) -> Result<Vec<Row>, QueryError> {
+        self.check_writable()?;
 
-        for row in &mut self.rows {
-            row.push(default.clone());
-        }
-        Ok(QueryResponse::Ok((self.columns.len() as Index) - 1))
+        let rows = mem::take(&mut self.rows);
+        self.rebuild_indexes();
+        self.emit_rows_deleted((0..rows.len() as Index).rev());
+        Ok(rows)
     }
 
-    /// Creates and appends a new row with the given data.
-    pub fn append_row(&mut self, data: Row) -> QueryResult {
-        if data.len() != self.columns.len() {
-            return Err(QueryError::DataMismatch);
-        }
-        for (i, val) in data.iter().enumerate() {
-            let restriction = self.columns[i].ty_restriction.clone();
+    /// Sorts the rows by each key in sequence, using [Data::total_cmp].
+    ///
+    /// Each key is a column index, followed by true for ascending order. Later keys break ties of
+    /// earlier keys, and rows equal under every key keep their order. Returns the original indices
+    /// of the rows in their new order.
+    pub fn sort_by_columns(&mut self, keys: &[(Index, bool)]) -> QueryResult {
+        self.check_writable()?;
 
-            if restriction != val.get_type() {
-                return Err(QueryError::TypeMismatch(restriction, val.get_type()));
-            }
-        }
-        self.rows.push(data);
-        Ok(QueryResponse::Ok((self.rows.len() as Index) - 1))
+        let keys = keys
+            .iter()
+            .map(|&(col, ascending)| self.check_column(col).map(|col| (col, ascending)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut order: Vec<_> = (0..self.rows.len()).collect();
+
+        order.sort_by(|&a, &b| {
+            let (a, b) = (&self.rows[a], &self.rows[b]);
+
+            keys.iter()
+                .map(|&(col, ascending)| {
+                    let ordering = a[col].total_cmp(&b[col]);
+                    if ascending { ordering } else { ordering.reverse() }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let mut rows: Vec<_> = mem::take(&mut self.rows).into_iter().map(Some).collect();
+        self.rows = order.iter().filter_map(|&i| rows[i].take()).collect();
+        self.rebuild_indexes();
+        self.emit(ChangeEvent::RowsReordered);
+        Ok(QueryResponse::ModifiedRows(order.into_iter().map(|i| i as Index).collect()))
     }
 
-    /// Fetches a singular value according to the given query.
-    pub fn fetch_value(&mut self, data_index: Index, row_index: Index) -> QueryResult {
-        if data_index as usize >= self.rows.len() {
-            return Err(QueryError::DataOutOfBounds);
+    /// Sets the primary key column, which identifies each row by a unique, non-`NULL` value.
+    ///
+    /// Returns an [Err] if the column already contains a `NULL` or repeated value.
+    pub fn set_primary_key(&mut self, col_index: Index) -> QueryResult {
+        self.check_writable()?;
+
+        let col = self.check_column(col_index)?;
+
+        if self.rows.iter().any(|row| row[col] == Data::Null) {
+            return Err(QueryError::NullConstraintViolation);
         }
-        let row = &self.rows[row_index as usize];
-        let data = &row[data_index as usize];
-        Ok(QueryResponse::OkSingle(data.clone()))
+        if self.group_rows(&[col]).len() != self.rows.len() {
+            return Err(QueryError::DuplicateKey);
+        }
+        self.primary_key = Some(col_index);
+        Ok(QueryResponse::Ok(col_index))
     }
 
-    /// Queries the database with the given instruction.
-    pub fn query(&mut self, instruction: Instruction) -> QueryResult {
-        match instruction {
-            Instruction::DeleteColumn(id) => match id {
-                Id::Name(name) => self.delete_column_by_name(&name),
-                Id::Index(i) => self.delete_column_by_index(i),
-            },
-            Instruction::DeleteRow(index) => self.delete_row_by_index(index),
-            Instruction::AppendColumn(name, ty) => self.append_column(name, ty),
-            Instruction::AppendRow(data) => self.append_row(data),
-            Instruction::Fetch(i_data, i_row) => self.fetch_value(i_data, i_row),
-        }
+    /// Removes the primary key, leaving the column intact.
+    pub fn clear_primary_key(&mut self) -> QueryResult {
+        self.check_writable()?;
+        self.primary_key = None;
+        Ok(QueryResponse::Ok(0))
     }
 
-    /// Wrapper method for [tora::write_to_file].
-    pub fn write_to_file<P>(&self, path: P) -> io::Result<()>
-    where P: AsRef<Path> {
-        tora::write_to_file(path, self)
+    /// Returns the index of the primary key column, if any.
+    pub const fn primary_key(&self) -> Option<Index> {
+        self.primary_key
     }
-    
-    /// Constructs a new Db.
-    pub const fn new(columns: Vec<Column>, rows: Vec<Row>) -> Self {
-        Self { columns, rows }
+
+    /// Returns the index of the row whose primary key equals the given key.
+    ///
+    /// Returns [None] if no primary key is set, or no row matches.
+    pub fn find_by_key(&self, key: &Data) -> Option<Index> {
+        let pk = self.primary_key?;
+        self.lookup(pk, key).ok()?.first().copied()
     }
-}
 
-/// The reason that the DB failed to load.
-#[derive(Display, Debug)]
-pub enum LoadDbErrorKind {
-    Malformed,
-    Io,
-}
+    /// Updates the given values of the row identified by the primary key, inserting a new row if
+    /// none matches.
+    ///
+    /// New rows hold the key, the given values, and the column defaults elsewhere. Every value is
+    /// validated before any change is made. Returns the index of the updated or inserted row, or an
+    /// [Err] if no primary key is set.
+    pub fn upsert_by_key(&mut self, key: Data, values: HashMap<Index, Data>) -> QueryResult {
+        self.check_writable()?;
 
-/// An error produced by the DB when loading itself from a byte source fails.
-#[derive(Display, Debug, Error)]
-#[display("{kind}: {message}")]
-pub struct LoadDbError {
-    message: String,
-    kind: LoadDbErrorKind,
-}
+        let pk = self.primary_key.ok_or(QueryError::NotFound)?;
+        let existing = self.find_by_key(&key);
 
-impl LoadDbError {
-    pub fn message(&self) -> &str {
-        &self.message
+        for (&col, val) in &values {
+            let offset = self.check_column(col)?;
+            self.check_cell(&self.columns[offset], val)?;
+
+            if col == pk && *val != key {
+                self.check_key(val, existing)?;
+            }
+        }
+        match existing {
+            Some(row) => {
+                let mut cols: Vec<_> = values.keys().copied().collect();
+                cols.sort_unstable();
+
+                for (col, val) in values {
+                    self.rows[row as usize][col as usize] = val;
+                }
+                self.rebuild_indexes();
+
+                for col in cols {
+                    self.emit(ChangeEvent::CellUpdated(row, col));
+                }
+                Ok(QueryResponse::Ok(row))
+            }
+            None => {
+                let mut data: Row = self.columns.iter().map(|col| col.default.clone()).collect();
+                data[pk as usize] = key;
+
+                for (col, val) in values {
+                    data[col as usize] = val;
+                }
+                self.check_row(&data)?;
+                self.push_row(data).map(QueryResponse::Ok)
+            }
+        }
     }
 
-    pub const fn kind(&self) -> &LoadDbErrorKind {
-        &self.kind
+    /// Deletes every row, leaving the columns intact.
+    pub fn truncate(&mut self) -> QueryResult {
+        self.check_writable()?;
+
+        let len = self.rows.len() as Index;
+        self.rows.clear();
+        self.rebuild_indexes();
+        self.emit_rows_deleted((0..len).rev());
+        Ok(QueryResponse::Ok(0))
     }
 
-    const fn new(message: String, kind: LoadDbErrorKind) -> Self {
-        Self { message, kind }
+    /// Replaces every row with the given rows, validating each as a new row.
+    ///
+    /// The rows are only replaced if all of them pass, otherwise the existing rows are left
+    /// untouched and the failure is returned as [QueryError::BatchFailed] with the position of the
+    /// row. Returns the indices of the new rows.
+    pub fn replace_all_rows(&mut self, rows: Vec<Row>) -> QueryResult {
+        self.check_writable()?;
+        if rows.len() > MAX_LEN {
+            return Err(QueryError::LimitExceeded);
+        }
+        let old = mem::replace(&mut self.rows, Vec::with_capacity(rows.len()));
+        self.rebuild_indexes();
+
+        for (i, row) in rows.into_iter().enumerate() {
+            if let Err(e) = self.check_row(&row) {
+                self.rows = old;
+                self.rebuild_indexes();
+                return Err(QueryError::BatchFailed(i as Index, Box::new(e)));
+            }
+            for idx in &mut self.indexes {
+                idx.insert(&row[idx.column as usize], i as Index);
+            }
+            self.rows.push(row);
+        }
+        let len = self.rows.len() as Index;
+        self.emit_rows_deleted((0..old.len() as Index).rev());
+
+        for row in 0..len {
+            self.emit(ChangeEvent::RowInserted(row));
+        }
+        Ok(QueryResponse::ModifiedRows((0..len).collect()))
     }
-}
 
-impl From<io::Error> for LoadDbError {
-    fn from(value: io::Error) -> Self {
-        if value.kind() == io::ErrorKind::InvalidData {
-            return Self::new(value.to_string(), LoadDbErrorKind::Malformed);
+    /// Deletes every column and row, along with every index.
+    pub fn clear(&mut self) -> QueryResult {
+        self.check_writable()?;
+
+        let (columns, rows) = (self.columns.len() as Index, self.rows.len() as Index);
+        self.columns.clear();
+        self.rows.clear();
+        self.indexes.clear();
+        self.primary_key = None;
+        self.emit_rows_deleted((0..rows).rev());
+
+        for col in (0..columns).rev() {
+            self.emit(ChangeEvent::ColumnDropped(col));
         }
-        Self::new(value.to_string(), LoadDbErrorKind::Io)
+        Ok(QueryResponse::Ok(0))
     }
-}
 
-impl TryFrom<&[u8]> for Db {
-    type Error = LoadDbError;
+    /// Removes every row whose values in the given key columns equal those of an earlier row.
+    ///
+    /// Returns the original indices of the removed rows.
+    pub fn deduplicate_on(&mut self, key_cols: &[Index]) -> QueryResult {
+        self.check_writable()?;
+        let cols = self.check_columns(key_cols)?;
+        let mut keep = vec![true; self.rows.len()];
+        let mut removed: Vec<_> = self
+            .group_rows(&cols)
+            .into_iter()
+            .flat_map(|group| group.into_iter().skip(1))
+            .collect();
 
+        removed.sort_unstable();
+
+        for &i in &removed {
+            keep[i as usize] = false;
+        }
+        let mut keep = keep.into_iter();
+        self.rows.retain(|_| keep.next().unwrap_or(true));
+        self.rebuild_indexes();
+        self.emit_rows_deleted(removed.iter().rev().copied());
+        Ok(QueryResponse::ModifiedRows(removed))
+    }
+
+    /// Creates and appends a new column with the given name and type restriction.
+    /// 
+    /// A `NULL` value will be appended to all rows.
+    pub fn append_column(&mut self, name: String, ty_restrict: Type) -> QueryResult {
+        self.append_column_default(name, ty_restrict, Data::Null)
+    }
+
+    /// Creates and appends a new column with the given name and type restriction.
+    ///
+    /// The provided default value will be appended to all rows. Returns an [Err] if it does not
+    /// satisfy the type restriction, or the name is invalid or used by another column.
+    pub fn append_column_default(&mut self, name: String, ty_restrict: Type, default: Data) -> QueryResult {
+        self.append_column_returning_index(name, ty_restrict, default)
+            .map(QueryResponse::Ok)
+    }
+
+    /// Creates and appends a new column like [Db::append_column_default], returning its index
+    /// directly.
+    pub fn append_column_returning_index(
+        &mut self,
+        name: String,
+        ty_restrict: Type,
+        default: Data,
+    ) -> Result<Index, QueryError> {
+        self.add_column(Column::new(name, ty_restrict), default)
+    }
+
+    /// Creates and appends a new column with the given name and type restriction, which rejects
+    /// `NULL` values.
+    ///
+    /// The provided default value will be appended to all rows, and used when a row omits the
+    /// column. Returns an [Err] if the default is `NULL`.
+    pub fn append_column_required(&mut self, name: String, ty: Type, default: Data) -> QueryResult {
+        self.append_column_def(Column::new_required(name, ty, default))
+    }
+
+    /// Appends the given column definition, keeping its constraints, default and metadata.
+    ///
+    /// The default of the column will be appended to all rows. Returns an [Err] if the name is
+    /// invalid or used by another column, or the default does not satisfy the restrictions of the
+    /// column.
+    pub fn append_column_def(&mut self, col: Column) -> QueryResult {
+        let backfill = col.default.clone();
+        self.add_column(col, backfill).map(QueryResponse::Ok)
+    }
+
+    /// Creates and appends a new column for each of the given names and type restrictions.
+    ///
+    /// All names are validated before any column is added, so a name that is invalid, repeated,
+    /// or already used by an existing column aborts the whole batch.
+    pub fn append_columns(&mut self, defs: Vec<(String, Type)>) -> QueryResult {
+        self.check_writable()?;
+        for (i, (name, _)) in defs.iter().enumerate() {
+            self.check_new_name(name)?;
+
+            if defs[..i].iter().any(|(other, _)| other == name) {
+                return Err(QueryError::DuplicateName(name.clone()));
+            }
+        }
+        if self.columns.len() + defs.len() > MAX_LEN {
+            return Err(QueryError::LimitExceeded);
+        }
+        let mut ids = Vec::with_capacity(defs.len());
+
+        for (name, ty) in defs {
+            let index = self.columns.len() as Index;
+            self.columns.push(Column::new(name, ty));
+
+            for row in &mut self.rows {
+                row.push(Data::Null);
+            }
+            self.column_added(index);
+            ids.push(Id::Index(index));
+        }
+        Ok(QueryResponse::ModifiedColumns(ids))
+    }
+
+    /// Creates and appends a new column with the given name, restricted to the type of the sample
+    /// value.
+    ///
+    /// The sample will be appended to all rows. Returns an [Err] if the sample is `NULL`, as its
+    /// type is ambiguous.
+    pub fn append_column_inferred(&mut self, name: String, sample: Data) -> QueryResult {
+        if sample == Data::Null {
+            return Err(QueryError::DataMismatch);
+        }
+        self.append_column_default(name, sample.get_type(), sample)
+    }
+
+    /// Creates and appends a new string column with the given name, limiting the length in bytes of
+    /// its values.
+    ///
+    /// A `NULL` value will be appended to all rows.
+    pub fn append_column_string(&mut self, name: String, max_len: Option<u32>) -> QueryResult {
+        self.append_column_def(Column::new_string(name, max_len))
+    }
+
+    /// Creates and appends a new column with the given name and type restriction, populated with
+    /// the given values.
+    ///
+    /// The values are appended to the existing rows in order, so there must be exactly one value
+    /// per row.
+    pub fn add_column_with_values(
+        &mut self,
+        name: String,
+        ty: Type,
+        values: Vec<Data>,
+    ) -> QueryResult {
+        self.check_writable()?;
+        if values.len() != self.rows.len() {
+            return Err(QueryError::DataMismatch);
+        }
+        let col = Column::new(name, ty);
+
+        for val in &values {
+            self.check_cell(&col, val)?;
+        }
+        self.check_new_name(&col.name)?;
+        next_index(self.columns.len())?;
+        Ok(QueryResponse::Ok(self.push_column(col, values)))
+    }
+
+    /// Creates and appends a new string column holding the values of two string columns joined
+    /// by the separator, treating `NULL` values as empty strings.
+    ///
+    /// Returns an [Err] if either source column is not restricted to strings.
+    pub fn concat_columns(&mut self, a: Index, b: Index, sep: &str, new_name: String) -> QueryResult {
+        let (x, y) = (self.check_column(a)?, self.check_column(b)?);
+
+        for col in [x, y] {
+            let ty = &self.columns[col].ty_restriction;
+
+            if *ty != Type::String {
+                return Err(QueryError::TypeMismatch(Type::String, ty.clone()));
+            }
+        }
+        let text = |val: &Data| match val {
+            Data::String(s) => s.clone(),
+            _ => String::new(),
+        };
+
+        let values = self
+            .rows
+            .iter()
+            .map(|row| Data::String(format!("{}{sep}{}", text(&row[x]), text(&row[y]))))
+            .collect();
+
+        self.add_column_with_values(new_name, Type::String, values)
+    }
+
+    /// Splits each value of a string column on the first occurrence of the separator, appending the
+    /// parts as two new string columns.
+    ///
+    /// Values without the separator are placed whole in the first new column, with `NULL` in the
+    /// second. The original column is deleted unless `keep_original` is set. Returns the indices of
+    /// the new columns, or an [Err] if the column is not restricted to strings.
+    pub fn split_column(
+        &mut self,
+        col_index: Index,
+        sep: &str,
+        new_names: (String, String),
+        keep_original: bool,
+    ) -> QueryResult {
+        self.check_writable()?;
+        let col = self.check_column(col_index)?;
+        let ty = &self.columns[col].ty_restriction;
+
+        if *ty != Type::String {
+            return Err(QueryError::TypeMismatch(Type::String, ty.clone()));
+        }
+        self.check_new_name(&new_names.0)?;
+        self.check_new_name(&new_names.1)?;
+
+        if new_names.0 == new_names.1 {
+            return Err(QueryError::DuplicateName(new_names.1));
+        }
+
+        if self.columns.len() + 2 > MAX_LEN {
+            return Err(QueryError::LimitExceeded);
+        }
+        let (first, second) = self
+            .rows
+            .iter()
+            .map(|row| match &row[col] {
+                Data::String(s) => match s.split_once(sep) {
+                    Some((a, b)) => (Data::from(a), Data::from(b)),
+                    None => (Data::String(s.clone()), Data::Null),
+                },
+                _ => (Data::Null, Data::Null),
+            })
+            .unzip();
+
+        let a = self.push_column(Column::new(new_names.0, Type::String), first);
+        let b = self.push_column(Column::new(new_names.1, Type::String), second);
+
+        if keep_original {
+            return Ok(QueryResponse::ModifiedColumns(vec![Id::Index(a), Id::Index(b)]));
+        }
+        self.remove_column(col_index);
+        Ok(QueryResponse::ModifiedColumns(vec![Id::Index(a - 1), Id::Index(b - 1)]))
+    }
+
+    /// Creates and appends a new row with the given data.
+    ///
+    /// If autopad is enabled, rows with fewer values than columns are padded like
+    /// [Db::append_row_partial]. If coercion on insert is enabled, values are first converted to
+    /// the type of their column where possible.
+    pub fn append_row(&mut self, mut data: Row) -> QueryResult {
+        if self.coerce_on_insert {
+            self.coerce_row(&mut data);
+        }
+        if self.autopad && data.len() < self.expected_row_len() {
+            return self.append_row_partial(data);
+        }
+        self.check_writable()?;
+        self.check_row(&data)?;
+        self.push_row(data).map(QueryResponse::Ok)
+    }
+
+    /// Creates and appends a new row like [Db::append_row], returning its primary key value as
+    /// [QueryResponse::OkSingle] if a primary key is set.
+    ///
+    /// Without a primary key, the response of [Db::append_row] is returned unchanged.
+    pub fn append_row_returning_key(&mut self, data: Row) -> QueryResult {
+        match (self.append_row(data)?, self.primary_key) {
+            (QueryResponse::Ok(row), Some(pk)) => {
+                Ok(QueryResponse::OkSingle(self.rows[row as usize][pk as usize].clone()))
+            }
+            (response, _) => Ok(response),
+        }
+    }
+
+    /// Appends a new row with the given data, without checking it against the columns.
+    ///
+    /// This is meant for importers whose rows were already validated. Storing values that violate
+    /// the schema or primary key leaves the database inconsistent, and the amount of values is
+    /// only asserted in debug builds.
+    pub fn append_row_unchecked(&mut self, data: Row) -> QueryResult {
+        debug_assert_eq!(data.len(), self.expected_row_len(), "row length mismatch");
+        self.check_writable()?;
+        self.push_row(data).map(QueryResponse::Ok)
+    }
+
+    /// Creates and inserts a new row with the given data before all existing rows.
+    ///
+    /// The row is validated like [Db::append_row], and existing rows are shifted down by one.
+    pub fn prepend_row(&mut self, data: Row) -> QueryResult {
+        self.insert_row_at(0, data)
+    }
+
+    /// Creates and inserts a new row with the given data at the given index.
+    ///
+    /// The row is validated like [Db::append_row], and rows at or after the index are shifted down
+    /// by one. Returns an [Err] if the index is greater than the amount of rows.
+    pub fn insert_row_at(&mut self, index: Index, data: Row) -> QueryResult {
+        self.check_writable()?;
+        if index as usize > self.rows.len() {
+            return Err(QueryError::IndexOutOfBounds);
+        }
+        self.check_row(&data)?;
+        next_index(self.rows.len())?;
+
+        for idx in &mut self.indexes {
+            idx.shift_from(index);
+            idx.insert(&data[idx.column as usize], index);
+        }
+        self.rows.insert(index as usize, data);
+        self.emit(ChangeEvent::RowInserted(index));
+        Ok(QueryResponse::Ok(index))
+    }
+
+    /// Validates and appends each row of the iterator, one at a time.
+    ///
+    /// This operation is not atomic: if a row fails, the rows before it stay appended and the
+    /// remaining rows are not consumed. The failure is returned as [QueryError::BatchFailed] with
+    /// the position of the row within the iterator.
+    pub fn append_rows_from_iter<I>(&mut self, iter: I) -> QueryResult
+    where I: Iterator<Item = Row> {
+        self.check_writable()?;
+        let mut appended = Vec::new();
+
+        for (i, row) in iter.enumerate() {
+            let index = self
+                .check_row(&row)
+                .and_then(|_| self.push_row(row))
+                .map_err(|e| QueryError::BatchFailed(i as Index, Box::new(e)))?;
+
+            appended.push(index);
+        }
+        Ok(QueryResponse::ModifiedRows(appended))
+    }
+
+    /// Validates and appends every row, or none of them.
+    ///
+    /// If a row fails, the rows appended before it are removed again and the failure is returned
+    /// as [QueryError::BatchFailed] with the position of the row within the batch. Each row is
+    /// checked against the rows before it in the batch, and observers are only notified once the
+    /// whole batch has been appended.
+    pub fn append_rows_transactional(&mut self, rows: Vec<Row>) -> QueryResult {
+        self.check_writable()?;
+        let start = self.rows.len();
+
+        for (i, row) in rows.into_iter().enumerate() {
+            if let Err(e) = self.check_row(&row).and_then(|_| self.store_row(row)) {
+                self.rows.truncate(start);
+                self.rebuild_indexes();
+                return Err(QueryError::BatchFailed(i as Index, Box::new(e)));
+            }
+        }
+        let appended: Vec<Index> = (start as Index..self.rows.len() as Index).collect();
+
+        for &row in &appended {
+            self.emit(ChangeEvent::RowInserted(row));
+        }
+        Ok(QueryResponse::ModifiedRows(appended))
+    }
+
+    /// Creates and appends a new row with the given data, filling any missing trailing values
+    /// with the default of their column.
+    ///
+    /// Returns an [Err] if more values are given than there are columns.
+    pub fn append_row_partial(&mut self, mut data: Row) -> QueryResult {
+        self.check_writable()?;
+        if data.len() > self.expected_row_len() {
+            return Err(QueryError::DataMismatch);
+        }
+        let missing = self.columns[data.len()..].iter().map(|col| col.default.clone());
+        data.extend(missing);
+        self.check_row(&data)?;
+        self.push_row(data).map(QueryResponse::Ok)
+    }
+
+    /// Stores a free-form metadata value, such as a unit or description, under the given key of
+    /// the column.
+    ///
+    /// Metadata is persisted along with the column, but does not affect type checking.
+    pub fn set_column_metadata(
+        &mut self,
+        col_index: Index,
+        key: String,
+        value: String,
+    ) -> QueryResult {
+        self.check_writable()?;
+        let col = self.check_column(col_index)?;
+        self.columns[col].set_metadata(key, value);
+        Ok(QueryResponse::Ok(col_index))
+    }
+
+    /// Returns the metadata value stored under the given key of the column, if any.
+    pub fn column_metadata(&self, col_index: Index, key: &str) -> Result<Option<&str>, QueryError> {
+        Ok(self.column_at(col_index)?.metadata(key))
+    }
+
+    /// Creates and appends a new row holding the default of each column.
+    ///
+    /// Returns an [Err] if the defaults do not form a valid row, such as when they repeat an
+    /// existing primary key.
+    pub fn append_default_row(&mut self) -> QueryResult {
+        self.append_row_partial(Vec::new())
+    }
+
+    /// Sets the value used to fill the given column when a row omits it.
+    ///
+    /// Returns an [Err] if the default does not satisfy the restrictions of the column.
+    pub fn set_column_default(&mut self, col_index: Index, default: Data) -> QueryResult {
+        self.check_writable()?;
+        let col = self.check_column(col_index)?;
+        self.check_cell(&self.columns[col], &default)?;
+        self.columns[col].default = default;
+        Ok(QueryResponse::Ok(col_index))
+    }
+
+    /// Overwrites every value in the given integer column with an arithmetic sequence, starting at
+    /// `start` in the first row and increasing by `step` per row.
+    ///
+    /// Returns an [Err] without changing anything if the column is not of type [Type::Int] or
+    /// [Type::Long], a value does not fit the type, or the column is the primary key and the
+    /// values would repeat.
+    pub fn fill_column_with_sequence(
+        &mut self,
+        col_index: Index,
+        start: i64,
+        step: i64,
+    ) -> QueryResult {
+        self.check_writable()?;
+        let col = self.check_column(col_index)?;
+        let ty = &self.columns[col].ty_restriction;
+
+        if !matches!(ty, Type::Int | Type::Long) {
+            return Err(QueryError::TypeMismatch(Type::Long, ty.clone()));
+        }
+        let values = (0..self.rows.len() as i64)
+            .map(|i| {
+                let val = step.checked_mul(i).and_then(|v| v.checked_add(start));
+
+                match (ty, val) {
+                    (Type::Int, Some(v)) => i32::try_from(v).ok().map(Data::Int),
+                    (_, v) => v.map(Data::Long),
+                }
+                .ok_or(QueryError::DataOutOfBounds)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if self.primary_key == Some(col_index) && step == 0 && values.len() > 1 {
+            return Err(QueryError::DuplicateKey);
+        }
+        for (row, val) in self.rows.iter_mut().zip(values) {
+            row[col] = val;
+        }
+        self.rebuild_indexes();
+
+        for row in 0..self.rows.len() as Index {
+            self.emit(ChangeEvent::CellUpdated(row, col_index));
+        }
+        Ok(QueryResponse::Ok(col_index))
+    }
+
+    /// Attempts to convert every value in the column to the given type.
+    ///
+    /// The column type and its values are only changed if every value converts losslessly,
+    /// otherwise the database is left untouched. Check [CastReport::is_success] for the outcome.
+    ///
+    /// The default of the column is converted along with its values. Returns an [Err] if the
+    /// default can not be converted, or the column is the primary key and its converted values
+    /// would no longer be unique.
+    pub fn try_cast_column(&mut self, col: Index, new_ty: Type) -> Result<CastReport, QueryError> {
+        self.check_writable()?;
+        let is_key = self.primary_key == Some(col);
+        let col = self.check_column(col)?;
+        let mut report = CastReport::default();
+        let mut converted = Vec::with_capacity(self.rows.len());
+
+        let max_len = self.columns[col].max_len;
+
+        let default = &self.columns[col].default;
+        let default = default
+            .coerce_to(&new_ty)
+            .ok_or_else(|| QueryError::TypeMismatch(new_ty.clone(), default.get_type()))?;
+
+        check_len(max_len, &default)?;
+        self.check_finite(&default)?;
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let coerced = row[col]
+                .coerce_to(&new_ty)
+                .filter(|val| check_len(max_len, val).is_ok() && self.check_finite(val).is_ok());
+
+            match coerced {
+                Some(val) => {
+                    report.converted.push(i as Index);
+                    converted.push(val);
+                }
+                None => report.failed.push((i as Index, row[col].clone())),
+            }
+        }
+        if is_key && count_distinct(&converted).len() != converted.len() {
+            return Err(QueryError::DuplicateKey);
+        }
+        if report.is_success() {
+            for (row, val) in self.rows.iter_mut().zip(converted) {
+                row[col] = val;
+            }
+            self.columns[col].ty_restriction = new_ty;
+            self.columns[col].default = default;
+            self.rebuild_indexes();
+
+            for &row in &report.converted {
+                self.emit(ChangeEvent::CellUpdated(row, col as Index));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Returns every row whose value in the given column matches the predicate.
+    pub fn select_where<F>(&self, col_index: Index, pred: F) -> Result<Vec<Row>, QueryError>
+    where F: Fn(&Data) -> bool {
+        let col = self.check_column(col_index)?;
+        Ok(self.rows.iter().filter(|row| pred(&row[col])).cloned().collect())
+    }
+
+    /// Returns every row matching all of the conditions, each comparing the value in a column
+    /// against the given value.
+    ///
+    /// Comparisons involving `NULL` never match. Returns an [Err] before visiting any rows if a
+    /// column index is out of bounds, or a value does not match the type of its column.
+    pub fn select_where_all(
+        &self,
+        conditions: Vec<(Index, Comparison, Data)>,
+    ) -> Result<Vec<Row>, QueryError> {
+        let conditions = self.check_conditions(conditions)?;
+
+        Ok(self
+            .rows
+            .iter()
+            .filter(|row| {
+                conditions
+                    .iter()
+                    .all(|(col, cmp, val)| cmp.evaluate(&row[*col], val) == Some(true))
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Returns every row matching at least one of the conditions, each comparing the value in a
+    /// column against the given value.
+    ///
+    /// If there are no conditions, every row is returned. Otherwise, conditions are validated and
+    /// evaluated like [Db::select_where_all].
+    pub fn select_where_any(
+        &self,
+        conditions: Vec<(Index, Comparison, Data)>,
+    ) -> Result<Vec<Row>, QueryError> {
+        if conditions.is_empty() {
+            return Ok(self.rows.clone());
+        }
+        let conditions = self.check_conditions(conditions)?;
+
+        Ok(self
+            .rows
+            .iter()
+            .filter(|row| {
+                conditions
+                    .iter()
+                    .any(|(col, cmp, val)| cmp.evaluate(&row[*col], val) == Some(true))
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Counts the rows whose value in the given column matches the predicate, without cloning them.
+    pub fn count_where<F>(&self, col_index: Index, pred: F) -> Result<usize, QueryError>
+    where F: Fn(&Data) -> bool {
+        let col = self.check_column(col_index)?;
+        Ok(self.rows.iter().filter(|row| pred(&row[col])).count())
+    }
+
+    /// Returns the indices of every row whose value in the given column matches the predicate.
+    pub fn rows_where_indices<F>(&self, col_index: Index, pred: F) -> Result<Vec<Index>, QueryError>
+    where F: Fn(&Data) -> bool {
+        let col = self.check_column(col_index)?;
+
+        Ok(self
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| pred(&row[col]))
+            .map(|(i, _)| i as Index)
+            .collect())
+    }
+
+    /// Narrows each [Type::Long] column to [Type::Int], and each [Type::Double] column to
+    /// [Type::Float], if every value in it and its default convert losslessly.
+    ///
+    /// Columns without any non-`NULL` values are left as they are. Returns the indices of the
+    /// narrowed columns.
+    pub fn optimize_types(&mut self) -> QueryResult {
+        self.check_writable()?;
+        let mut narrowed = Vec::new();
+
+        for col in 0..self.columns.len() {
+            let narrower = match self.columns[col].ty_restriction {
+                Type::Long => Type::Int,
+                Type::Double => Type::Float,
+                _ => continue,
+            };
+            if self.rows.iter().all(|row| row[col] == Data::Null) {
+                continue;
+            }
+            if self.columns[col].default.coerce_to(&narrower).is_none() {
+                continue;
+            }
+            if self.try_cast_column(col as Index, narrower)?.is_success() {
+                narrowed.push(Id::Index(col as Index));
+            }
+        }
+        Ok(QueryResponse::ModifiedColumns(narrowed))
+    }
+
+    /// Renames and/or changes the type of the given column in one operation.
+    ///
+    /// The type is changed first, so if any value fails to convert, or the new name is used by
+    /// another column, the column is left untouched.
+    pub fn alter_column(
+        &mut self,
+        col_index: Index,
+        new_name: Option<String>,
+        new_type: Option<Type>,
+    ) -> QueryResult {
+        self.check_writable()?;
+        let col = self.check_column(col_index)?;
+
+        if let Some(name) = &new_name {
+            check_name(name)?;
+
+            let taken = self
+                .columns
+                .iter()
+                .enumerate()
+                .any(|(i, c)| i != col && c.name == *name);
+
+            if taken {
+                return Err(QueryError::DuplicateName(name.clone()));
+            }
+        }
+        if let Some(ty) = new_type {
+            let report = self.try_cast_column(col_index, ty.clone())?;
+
+            if let Some((_, val)) = report.failed().first() {
+                return Err(QueryError::TypeMismatch(ty, val.get_type()));
+            }
+        }
+        if let Some(name) = new_name {
+            self.columns[col].name = name;
+            self.emit(ChangeEvent::ColumnRenamed(col_index));
+        }
+        Ok(QueryResponse::Ok(col_index))
+    }
+
+    /// Renames several columns in one operation.
+    ///
+    /// Uniqueness is only checked against the final set of names, so columns may swap or cycle
+    /// their names. Returns an [Err] without renaming anything if an index is out of bounds, or a
+    /// resulting name is invalid or used by more than one column. An index given more than once
+    /// returns [QueryError::RepeatedColumn].
+    pub fn rename_columns(&mut self, renames: Vec<(Index, String)>) -> QueryResult {
+        self.check_writable()?;
+        let mut names: Vec<&str> = self.columns.iter().map(|col| col.name.as_str()).collect();
+        let mut renamed = vec![false; names.len()];
+
+        for (index, name) in &renames {
+            let col = self.check_column(*index)?;
+            check_name(name)?;
+
+            if mem::replace(&mut renamed[col], true) {
+                return Err(QueryError::RepeatedColumn(*index));
+            }
+            names[col] = name;
+        }
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(QueryError::DuplicateName(name.to_string()));
+            }
+        }
+        let mut ids = Vec::with_capacity(renames.len());
+
+        for (index, name) in renames {
+            self.columns[index as usize].name = name;
+            self.emit(ChangeEvent::ColumnRenamed(index));
+            ids.push(Id::Index(index));
+        }
+        Ok(QueryResponse::ModifiedColumns(ids))
+    }
+
+    /// Returns the given columns of every row whose value in the filter column matches the
+    /// predicate.
+    ///
+    /// All indices are validated before any rows are visited.
+    pub fn select_columns_where<F>(
+        &self,
+        cols: &[Index],
+        filter_col: Index,
+        pred: F,
+    ) -> Result<Vec<Row>, QueryError>
+    where F: Fn(&Data) -> bool {
+        let cols = self.check_columns(cols)?;
+        let filter_col = self.check_column(filter_col)?;
+
+        Ok(self
+            .rows
+            .iter()
+            .filter(|row| pred(&row[filter_col]))
+            .map(|row| cols.iter().map(|&i| row[i].clone()).collect())
+            .collect())
+    }
+
+    /// Creates a hash index over the given column, speeding up [Db::lookup].
+    ///
+    /// Creating an index over an already indexed column rebuilds it.
+    pub fn create_index(&mut self, col: Index) -> QueryResult {
+        self.check_column(col)?;
+        self.indexes.retain(|idx| idx.column != col);
+        self.indexes.push(HashIndex::build(col, &self.rows));
+        Ok(QueryResponse::Ok(col))
+    }
+
+    /// Drops the hash index over the given column.
+    ///
+    /// Returns an [Err] if the column is not indexed.
+    pub fn drop_index(&mut self, col: Index) -> QueryResult {
+        match self.indexes.iter().position(|idx| idx.column == col) {
+            Some(i) => {
+                self.indexes.remove(i);
+                Ok(QueryResponse::Ok(col))
+            }
+            None => Err(QueryError::NotFound),
+        }
+    }
+
+    /// Returns true if the given column has a hash index.
+    pub fn has_index(&self, col: Index) -> bool {
+        self.indexes.iter().any(|idx| idx.column == col)
+    }
+
+    /// Returns the indices of the rows whose value in the given column equals the value.
+    ///
+    /// Uses the column's hash index if one exists, otherwise scans every row.
+    pub fn lookup(&self, col: Index, value: &Data) -> Result<Vec<Index>, QueryError> {
+        let offset = self.check_column(col)?;
+
+        if let Some(index) = self.indexes.iter().find(|idx| idx.column == col) {
+            return Ok(index
+                .candidates(value)
+                .iter()
+                .copied()
+                .filter(|&i| self.rows[i as usize][offset] == *value)
+                .collect());
+        }
+        Ok(self
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row[offset] == *value)
+            .map(|(i, _)| i as Index)
+            .collect())
+    }
+
+    /// Converts every row into [T], in order.
+    ///
+    /// [T] is expected to map the row's values positionally, for example by using [row_value] for
+    /// each of its fields. The first conversion failure is returned.
+    pub fn rows_as<T>(&self) -> Result<Vec<T>, QueryError>
+    where T: for<'a> TryFrom<&'a Row, Error = QueryError> {
+        self.rows.iter().map(T::try_from).collect()
+    }
+
+    /// Returns the index of the first row which is identical to the given values.
+    pub fn find_row(&self, data: &[Data]) -> Option<Index> {
+        self.rows.iter().position(|row| row == data).map(|i| i as Index)
+    }
+
+    /// Returns the groups of row indices which share the same values in the given columns.
+    ///
+    /// Only groups of two or more rows are returned, in order of first occurrence.
+    pub fn find_duplicates(&self, cols: &[Index]) -> Result<Vec<Vec<Index>>, QueryError> {
+        let cols = self.check_columns(cols)?;
+
+        Ok(self
+            .group_rows(&cols)
+            .into_iter()
+            .filter(|group| group.len() >= 2)
+            .collect())
+    }
+
+    /// Counts the distinct non-`NULL` values in the given column.
+    pub fn column_cardinality(&self, col_index: Index) -> Result<usize, QueryError> {
+        let col = self.check_column(col_index)?;
+        let values = self.rows.iter().map(|row| &row[col]);
+        Ok(count_distinct(values.filter(|val| **val != Data::Null)).len())
+    }
+
+    /// Counts the distinct non-`NULL` values in one column, among the rows whose value in another
+    /// column matches the predicate.
+    pub fn count_distinct_where<F>(
+        &self,
+        distinct_col: Index,
+        filter_col: Index,
+        pred: F,
+    ) -> Result<usize, QueryError>
+    where F: Fn(&Data) -> bool {
+        let (col, filter) = (self.check_column(distinct_col)?, self.check_column(filter_col)?);
+
+        let values = self
+            .rows
+            .iter()
+            .filter(|row| pred(&row[filter]))
+            .map(|row| &row[col]);
+
+        Ok(count_distinct(values.filter(|val| **val != Data::Null)).len())
+    }
+
+    /// Returns whether every non-`NULL` value in the given column is distinct.
+    pub fn column_is_unique(&self, col_index: Index) -> Result<bool, QueryError> {
+        let col = self.check_column(col_index)?;
+        let values = self.rows.iter().map(|row| &row[col]).filter(|val| **val != Data::Null);
+        Ok(count_distinct(values).iter().all(|&(_, count)| count == 1))
+    }
+
+    /// Counts the `NULL` values in the given column.
+    pub fn column_null_count(&self, col_index: Index) -> Result<usize, QueryError> {
+        self.count_where(col_index, |val| *val == Data::Null)
+    }
+
+    /// Counts the `NULL` values in every column.
+    pub fn total_null_count(&self) -> usize {
+        self.iter_cells().filter(|(_, _, val)| **val == Data::Null).count()
+    }
+
+    /// Returns each distinct value in the given column along with the amount of times it occurs,
+    /// sorted by descending count.
+    ///
+    /// `NULL` values are counted as their own value. Values with equal counts are ordered by first
+    /// occurrence.
+    pub fn column_histogram(&self, col_index: Index) -> Result<Vec<(Data, usize)>, QueryError> {
+        let col = self.check_column(col_index)?;
+
+        let mut histogram: Vec<_> = count_distinct(self.rows.iter().map(|row| &row[col]))
+            .into_iter()
+            .map(|(val, count)| (val.clone(), count))
+            .collect();
+
+        histogram.sort_by_key(|&(_, count)| Reverse(count));
+        Ok(histogram)
+    }
+
+    /// Fetches a singular value according to the given query.
+    ///
+    /// Returns an [Err] if the row index is out of bounds, or if the data index is greater than or
+    /// equal to the amount of columns.
+    pub fn fetch_value(&self, data_index: Index, row_index: Index) -> QueryResult {
+        let row = self
+            .rows
+            .get(row_index as usize)
+            .ok_or(QueryError::IndexOutOfBounds)?;
+
+        let data = row
+            .get(data_index as usize)
+            .ok_or(QueryError::DataOutOfBounds)?;
+
+        Ok(QueryResponse::OkSingle(data.clone()))
+    }
+
+    /// Fetches the rows in the range `[start, end)`, clamping `end` to the amount of rows.
+    ///
+    /// Returns an [Err] if `start` is greater than the amount of rows. If `end` is not greater than
+    /// `start`, no rows are returned.
+    pub fn fetch_range(&self, start: Index, end: Index) -> Result<Vec<Row>, QueryError> {
+        let start = start as usize;
+
+        if start > self.rows.len() {
+            return Err(QueryError::IndexOutOfBounds);
+        }
+        let end = (end as usize).clamp(start, self.rows.len());
+        Ok(self.rows[start..end].to_vec())
+    }
+
+    /// Fetches a singular value from the column which exactly matches the given name.
+    pub fn fetch_value_by_name(&self, row_index: Index, col_name: &str) -> QueryResult {
+        let data_index = self.resolve_column(&Id::Name(col_name.to_string()))?;
+        self.fetch_value(data_index, row_index)
+    }
+
+    /// Queries the database with the given instruction.
+    pub fn query(&mut self, instruction: Instruction) -> QueryResult {
+        match instruction {
+            Instruction::DeleteColumn(id) => match id {
+                Id::Name(name) => self.delete_column_by_name(&name),
+                Id::Index(i) => self.delete_column_by_index(i),
+            },
+            Instruction::DeleteRow(index) => self.delete_row_by_index(index),
+            Instruction::AppendColumn(name, ty) => self.append_column(name, ty),
+            Instruction::AppendRow(data) => self.append_row(data),
+            Instruction::Fetch(i_data, i_row) => self.fetch_value(i_data, i_row),
+            Instruction::CreateIndex(id) => self.create_index(self.resolve_column(&id)?),
+            Instruction::DropIndex(id) => self.drop_index(self.resolve_column(&id)?),
+            Instruction::Truncate => self.truncate(),
+            Instruction::Clear => self.clear(),
+            Instruction::AppendColumnDef(col) => self.append_column_def(col),
+            Instruction::SetPrimaryKey(id) => self.set_primary_key(self.resolve_column(&id)?),
+        }
+    }
+
+    /// Queries the database with the given read-only instruction, through a shared reference.
+    ///
+    /// Returns [QueryError::ReadOnly] for instructions which would modify the database.
+    pub fn query_ref(&self, instruction: &Instruction) -> QueryResult {
+        match instruction {
+            Instruction::Fetch(i_data, i_row) => self.fetch_value(*i_data, *i_row),
+            _ => Err(QueryError::ReadOnly),
+        }
+    }
+
+    /// Queries the database with each instruction in order, stopping at the first failure.
+    ///
+    /// The failure is returned as [QueryError::BatchFailed] with the position of the instruction.
+    /// Instructions before it stay applied.
+    pub fn apply_all(
+        &mut self,
+        instructions: Vec<Instruction>,
+    ) -> Result<Vec<QueryResponse>, QueryError> {
+        instructions
+            .into_iter()
+            .enumerate()
+            .map(|(i, instruction)| {
+                self.query(instruction)
+                    .map_err(|e| QueryError::BatchFailed(i as Index, Box::new(e)))
+            })
+            .collect()
+    }
+
+    /// Queries the database with every instruction in order, continuing past failures.
+    ///
+    /// Returns the result of each instruction, in the same order.
+    pub fn apply_all_best_effort(&mut self, instructions: Vec<Instruction>) -> Vec<QueryResult> {
+        instructions
+            .into_iter()
+            .map(|instruction| self.query(instruction))
+            .collect()
+    }
+
+    /// Returns the instructions which rebuild this database when applied to an empty one.
+    ///
+    /// Every column is appended first along with its constraints, default and metadata, followed
+    /// by every row and then the primary key. Applying them with [Db::apply_all] produces a
+    /// database equal to this one. Indexes and settings such as read-only mode are not included.
+    pub fn export_instructions(&self) -> Vec<Instruction> {
+        let columns = self.columns.iter().cloned().map(Instruction::AppendColumnDef);
+        let rows = self.rows.iter().cloned().map(Instruction::AppendRow);
+
+        let primary_key = self
+            .primary_key
+            .map(|pk| Instruction::SetPrimaryKey(Id::Index(pk)));
+
+        columns.chain(rows).chain(primary_key).collect()
+    }
+
+    /// Returns the columns of this database.
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// Creates a cursor returning the rows in pages of the given size.
+    ///
+    /// A page size of 0 is treated as 1.
+    pub fn cursor(&self, page_size: usize) -> RowCursor<'_> {
+        RowCursor {
+            rows: &self.rows,
+            page_size: page_size.max(1),
+            position: 0,
+        }
+    }
+
+    /// Returns the column at the given index, or an [Err] if it is out of bounds.
+    pub fn column_at(&self, index: Index) -> Result<&Column, QueryError> {
+        let col = self.check_column(index)?;
+        Ok(&self.columns[col])
+    }
+
+    /// Returns a map from each column name to its index.
+    pub fn column_index_map(&self) -> HashMap<String, Index> {
+        self.columns
+            .iter()
+            .enumerate()
+            .map(|(i, col)| (col.name.clone(), i as Index))
+            .collect()
+    }
+
+    /// Returns the rows of this database.
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// Returns the values of each column, in column order.
+    pub fn transpose(&self) -> Vec<Vec<Data>> {
+        (0..self.columns.len())
+            .map(|col| self.rows.iter().map(|row| row[col].clone()).collect())
+            .collect()
+    }
+
+    /// Iterates over every value in row-major order, along with its row and column index.
+    ///
+    /// ```
+    /// use tora_db::engine::Db;
+    /// use tora_db::{Data, Type};
+    ///
+    /// let mut db = Db::default();
+    /// db.append_columns(vec![("A".to_string(), Type::Int), ("B".to_string(), Type::Int)])
+    ///     .unwrap();
+    /// db.append_row(vec![Data::Int(1), Data::Int(2)]).unwrap();
+    /// db.append_row(vec![Data::Int(3), Data::Int(4)]).unwrap();
+    ///
+    /// let cells: Vec<_> = db.iter_cells().collect();
+    /// assert_eq!(cells, [
+    ///     (0, 0, &Data::Int(1)),
+    ///     (0, 1, &Data::Int(2)),
+    ///     (1, 0, &Data::Int(3)),
+    ///     (1, 1, &Data::Int(4)),
+    /// ]);
+    /// ```
+    pub fn iter_cells(&self) -> impl Iterator<Item = (Index, Index, &Data)> {
+        self.rows.iter().enumerate().flat_map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(j, val)| (i as Index, j as Index, val))
+        })
+    }
+
+    /// Returns `n` randomly chosen rows, in random order.
+    ///
+    /// The same seed always produces the same sample. If `n` is not less than the amount of rows,
+    /// every row is returned shuffled.
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<Row> {
+        let mut rng = XorShift64::new(seed);
+        let mut order: Vec<_> = (0..self.rows.len()).collect();
+        let n = n.min(order.len());
+
+        for i in 0..n {
+            let j = i + (rng.next() % (order.len() - i) as u64) as usize;
+            order.swap(i, j);
+        }
+        order[..n].iter().map(|&i| self.rows[i].clone()).collect()
+    }
+
+    /// Returns the first row, if any.
+    pub fn first_row(&self) -> Option<&Row> {
+        self.rows.first()
+    }
+
+    /// Returns the last row, if any.
+    pub fn last_row(&self) -> Option<&Row> {
+        self.rows.last()
+    }
+
+    /// Returns the amount of values a row must contain, which is the amount of columns.
+    pub fn expected_row_len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Returns the column names joined by the given delimiter.
+    ///
+    /// Names containing the delimiter, a quote or a line break are quoted, with any quotes
+    /// doubled.
+    pub fn header_line(&self, delimiter: char) -> String {
+        let names: Vec<_> = self
+            .columns
+            .iter()
+            .map(|col| quote_field(&col.name, delimiter))
+            .collect();
+
+        names.join(&delimiter.to_string())
+    }
+
+    /// Sets whether the database is read-only, rejecting every operation which modifies its
+    /// columns or rows with [QueryError::ReadOnly].
+    ///
+    /// Fetches, selects and indexes are unaffected.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Sets whether [Db::append_row] pads rows with fewer values than columns, instead of
+    /// rejecting them.
+    ///
+    /// Missing values are filled with the default of their column, which is `NULL` unless
+    /// configured otherwise. Rows with too many values are still rejected.
+    pub fn set_autopad(&mut self, autopad: bool) {
+        self.autopad = autopad;
+    }
+
+    /// Sets whether inserting a row identical to an existing row is rejected with
+    /// [QueryError::DuplicateRow].
+    ///
+    /// Every insert then compares the new row against all existing rows.
+    pub fn set_reject_duplicate_rows(&mut self, reject: bool) {
+        self.reject_duplicate_rows = reject;
+    }
+
+    /// Sets whether [Db::append_row] converts values to the type of their column before checking
+    /// them, using [Data::coerce_to].
+    ///
+    /// Values which cannot be converted losslessly are left as they are, and still rejected.
+    pub fn set_coerce_on_insert(&mut self, coerce: bool) {
+        self.coerce_on_insert = coerce;
+    }
+
+    /// Sets whether serializing this database first runs [Db::validate], failing with
+    /// [io::ErrorKind::InvalidData] instead of writing anything if it is invalid.
+    pub fn set_validate_on_write(&mut self, validate: bool) {
+        self.validate_on_write = validate;
+    }
+
+    /// Sets whether a hash index is created for each newly appended column, as by
+    /// [Db::create_index].
+    ///
+    /// Existing columns are not affected.
+    pub fn set_auto_index(&mut self, auto_index: bool) {
+        self.auto_index = auto_index;
+    }
+
+    /// Sets whether storing a `NaN` or infinite floating point value is rejected with
+    /// [QueryError::NonFinite].
+    ///
+    /// This covers inserted rows, updated cells, column backfills and defaults. Casts with
+    /// [Db::try_cast_column] report non-finite results as failures. Values which are already
+    /// stored are left as they are.
+    pub fn set_reject_non_finite(&mut self, reject: bool) {
+        self.reject_non_finite = reject;
+    }
+
+    /// Returns true if the database is read-only.
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Reserves capacity for at least `additional` more rows, avoiding reallocation while appending.
+    pub fn reserve_rows(&mut self, additional: usize) {
+        self.rows.reserve(additional);
+    }
+
+    /// Returns the amount of rows the database can hold without reallocating.
+    pub fn row_capacity(&self) -> usize {
+        self.rows.capacity()
+    }
+
+    /// Registers an observer which is called after each successful change to the database.
+    pub fn on_change(&mut self, f: Observer) {
+        self.observers.push(f);
+    }
+
+    /// Estimates the amount of memory used by this database, in bytes.
+    ///
+    /// This accounts for the allocated capacity of the columns, rows, string values and indexes,
+    /// but not for allocator overhead or the observers.
+    pub fn memory_footprint(&self) -> usize {
+        let columns: usize = self
+            .columns
+            .iter()
+            .map(|col| {
+                let entries = col.metadata.capacity() * mem::size_of::<(String, String)>();
+                let metadata: usize = col
+                    .metadata
+                    .iter()
+                    .map(|(k, v)| k.capacity() + v.capacity())
+                    .sum();
+
+                col.name.capacity() + data_heap_size(&col.default) + entries + metadata
+            })
+            .sum();
+
+        let rows: usize = self
+            .rows
+            .iter()
+            .map(|row| {
+                let values: usize = row.iter().map(data_heap_size).sum();
+                row.capacity() * mem::size_of::<Data>() + values
+            })
+            .sum();
+
+        let indexes: usize = self
+            .indexes
+            .iter()
+            .map(|idx| {
+                let entries = idx.buckets.capacity() * mem::size_of::<(u64, Vec<Index>)>();
+                let rows: usize = idx.buckets.values().map(Vec::capacity).sum();
+                entries + rows * mem::size_of::<Index>()
+            })
+            .sum();
+
+        mem::size_of::<Self>()
+            + self.columns.capacity() * mem::size_of::<Column>()
+            + self.rows.capacity() * mem::size_of::<Row>()
+            + self.indexes.capacity() * mem::size_of::<HashIndex>()
+            + columns
+            + rows
+            + indexes
+    }
+
+    /// Checks that the database upholds every invariant normally enforced by its operations.
+    ///
+    /// Column names must be valid and unique, every row must satisfy the restrictions of the
+    /// columns, and the primary key values must be unique and not `NULL`. This is only necessary
+    /// for databases built through [Db::new].
+    pub fn validate(&self) -> Result<(), QueryError> {
+        for (i, col) in self.columns.iter().enumerate() {
+            check_name(&col.name)?;
+
+            if self.columns[..i].iter().any(|c| c.name == col.name) {
+                return Err(QueryError::DuplicateName(col.name.clone()));
+            }
+        }
+        for row in &self.rows {
+            if row.len() != self.expected_row_len() {
+                return Err(QueryError::DataMismatch);
+            }
+            for (col, val) in self.columns.iter().zip(row) {
+                check_value(col, val)?;
+            }
+        }
+        if let Some(pk) = self.primary_key {
+            let col = self.check_column(pk)?;
+
+            if self.rows.iter().any(|row| row[col] == Data::Null) {
+                return Err(QueryError::NullConstraintViolation);
+            }
+            if self.group_rows(&[col]).len() != self.rows.len() {
+                return Err(QueryError::DuplicateKey);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares the schema of this database against another, matching columns by name.
+    pub fn compare_schema(&self, other: &Db) -> SchemaDiff {
+        let mut diff = SchemaDiff::default();
+
+        for col in &self.columns {
+            match other.columns.iter().find(|c| c.name == col.name) {
+                Some(c) if c.ty_restriction != col.ty_restriction => diff.type_changed.push((
+                    col.name.clone(),
+                    col.ty_restriction.clone(),
+                    c.ty_restriction.clone(),
+                )),
+                Some(_) => {}
+                None => diff.only_in_self.push(col.name.clone()),
+            }
+        }
+        for col in &other.columns {
+            if !self.columns.iter().any(|c| c.name == col.name) {
+                diff.only_in_other.push(col.name.clone());
+            }
+        }
+        diff
+    }
+
+    /// Returns true if both databases have the same column names and types, in the same order.
+    pub fn schema_compatible_with(&self, other: &Db) -> bool {
+        self.columns.len() == other.columns.len()
+            && self.columns.iter().zip(&other.columns).all(|(a, b)| {
+                a.name == b.name && a.ty_restriction == b.ty_restriction
+            })
+    }
+
+    /// Compares this database against another, matching columns by name and type regardless of
+    /// their order.
+    ///
+    /// Rows are still compared in order.
+    pub fn eq_unordered(&self, other: &Db) -> bool {
+        if self.columns.len() != other.columns.len() || self.rows.len() != other.rows.len() {
+            return false;
+        }
+        let mut used = vec![false; other.columns.len()];
+        let mut mapping = Vec::with_capacity(self.columns.len());
+
+        for col in &self.columns {
+            let found = other.columns.iter().enumerate().position(|(i, c)| {
+                !used[i] && c.name == col.name && c.ty_restriction == col.ty_restriction
+            });
+
+            match found {
+                Some(i) => {
+                    used[i] = true;
+                    mapping.push(i);
+                }
+                None => return false,
+            }
+        }
+        self.rows.iter().zip(&other.rows).all(|(row, other_row)| {
+            mapping
+                .iter()
+                .enumerate()
+                .all(|(i, &j)| row[i] == other_row[j])
+        })
+    }
+
+    /// Generates SQL recreating this database as a table with the given name.
+    ///
+    /// The output is a `CREATE TABLE` statement followed by one `INSERT` statement per row, each
+    /// on its own line. Identifiers are double-quoted, and string literals are single-quoted.
+    /// Infinite and NaN values have no SQL literal and are written as `NULL`.
+    pub fn to_sql_inserts(&self, table_name: &str) -> String {
+        let table = quote_sql_ident(table_name);
+
+        let defs: Vec<_> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, col)| {
+                let mut def = format!("{} {}", quote_sql_ident(&col.name), sql_type(col));
+
+                if !col.nullable {
+                    def.push_str(" NOT NULL");
+                }
+                if self.primary_key == Some(i as Index) {
+                    def.push_str(" PRIMARY KEY");
+                }
+                def
+            })
+            .collect();
+
+        let mut sql = format!("CREATE TABLE {table} ({});\n", defs.join(", "));
+
+        for row in &self.rows {
+            let values: Vec<_> = row.iter().map(sql_literal).collect();
+            sql.push_str(&format!("INSERT INTO {table} VALUES ({});\n", values.join(", ")));
+        }
+        sql
+    }
+
+    /// Renders the columns and rows as a text table, with each column padded to its widest value.
+    pub fn render_table(&self, options: &DisplayOptions) -> String {
+        let header: Vec<_> = self.columns.iter().map(|col| col.name.clone()).collect();
+        let rows: Vec<Vec<_>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(|val| options.render(val)).collect())
+            .collect();
+
+        let mut widths: Vec<_> = header.iter().map(|name| name.chars().count()).collect();
+
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let render_line = |cells: &[String]| {
+            let padded: Vec<_> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{cell:<width$}"))
+                .collect();
+
+            padded.join(" | ").trim_end().to_string() + "\n"
+        };
+        let separator: Vec<_> = widths.iter().map(|&width| "-".repeat(width)).collect();
+
+        let mut table = render_line(&header);
+        table += &separator.join("-+-");
+        table.push('\n');
+
+        for row in &rows {
+            table += &render_line(row);
+        }
+        table
+    }
+
+    /// Renders the given row as `name=value` pairs joined by commas, for logging.
+    pub fn row_to_string(&self, row_index: Index) -> Result<String, QueryError> {
+        let row = self
+            .rows
+            .get(row_index as usize)
+            .ok_or(QueryError::IndexOutOfBounds)?;
+
+        let pairs: Vec<_> = self
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(col, val)| format!("{}={val}", col.name))
+            .collect();
+
+        Ok(pairs.join(", "))
+    }
+
+    /// Returns true if the database contains no rows.
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Computes a hash of the schema and all row data.
+    ///
+    /// The checksum is computed with 64-bit FNV-1a over fixed-width little-endian encodings of
+    /// each field, so identical databases produce the same value across runs, platforms and Rust
+    /// versions.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write_len(self.columns.len());
+
+        for col in &self.columns {
+            hasher.write_column(col);
+        }
+        hasher.write_len(self.rows.len());
+
+        for row in &self.rows {
+            hasher.write_row(row);
+        }
+        hasher.finish()
+    }
+
+    /// Computes a hash of the values of the given row.
+    ///
+    /// Like [Db::checksum], the hash uses FNV-1a over fixed-width encodings and is stable across
+    /// runs, platforms and Rust versions, so it only changes when the row does.
+    pub fn row_hash(&self, row_index: Index) -> Result<u64, QueryError> {
+        let row = self.rows.get(row_index as usize).ok_or(QueryError::IndexOutOfBounds)?;
+
+        let mut hasher = Fnv1a::new();
+        hasher.write_row(row);
+        Ok(hasher.finish())
+    }
+
+    /// Checks that the row could be appended, without appending it.
+    ///
+    /// This runs the same checks as [Db::append_row], including the primary key and duplicate
+    /// row checks, but does not pad or coerce the row.
+    pub fn validate_row(&self, data: &Row) -> Result<(), QueryError> {
+        self.check_row(data)
+    }
+
+    /// Serializes this database into the given writer.
+    pub fn write_to_writer<W>(&self, mut w: W) -> io::Result<()>
+    where W: Write {
+        w.writes(self)
+    }
+
+    /// Serializes this database into a buffer, using the same encoding as [Db::write_to_file].
+    pub fn to_tora_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.estimate_file_size());
+        bytes.writes(self)?;
+        Ok(bytes)
+    }
+
+    /// Estimates the amount of bytes written when serializing this database.
+    pub fn estimate_file_size(&self) -> usize {
+        let columns: usize = self
+            .columns
+            .iter()
+            .map(|col| {
+                let max_len = 1 + col.max_len.map_or(0, |_| LEN_SIZE);
+                let metadata = col.metadata.iter().map(|(k, v)| k.len() + 1 + v.len() + 1);
+                let metadata = LEN_SIZE + metadata.sum::<usize>();
+                col.name.len() + 1 + TAG_SIZE + max_len + data_size(&col.default) + 1 + metadata
+            })
+            .sum();
+
+        let rows: usize = self
+            .rows
+            .iter()
+            .map(|row| LEN_SIZE + row.iter().map(data_size).sum::<usize>())
+            .sum();
+
+        let primary_key = 1 + self.primary_key.map_or(0, |_| LEN_SIZE);
+        MAGIC.len() + 1 + LEN_SIZE + columns + LEN_SIZE + rows + primary_key
+    }
+
+    /// Wrapper method for [tora::write_to_file].
+    pub fn write_to_file<P>(&self, path: P) -> io::Result<()>
+    where P: AsRef<Path> {
+        tora::write_to_file(path, self)
+    }
+
+    /// Wrapper method for [Db::write_to_file], appending the `.tdb` extension if the path has no
+    /// extension.
+    pub fn write_to_file_auto_ext<P>(&self, path: P) -> io::Result<()>
+    where P: AsRef<Path> {
+        self.write_to_file(with_default_extension(path.as_ref()))
+    }
+
+    /// Reads a database from the file at the given path, appending the `.tdb` extension if the
+    /// path has no extension.
+    pub fn read_from_file_auto_ext<P>(path: P) -> Result<Self, LoadDbError>
+    where P: AsRef<Path> {
+        Ok(tora::read_from_file(with_default_extension(path.as_ref()))?)
+    }
+
+    /// Reads a comma-separated table whose first record holds the column names, inferring the
+    /// type of each column from the first [CSV_INFER_ROWS] records.
+    ///
+    /// Columns holding only integers become [Type::Int], or [Type::Long] if a value does not fit,
+    /// and columns holding any other number become [Type::Double]. Everything else becomes
+    /// [Type::String]. Empty fields are read as `NULL`. Returns [QueryError::DataMismatch] if the
+    /// input cannot be read or is not valid UTF-8, and [QueryError::BatchFailed] with the position
+    /// of the record if a record has a different amount of fields than the header, or does not fit
+    /// the inferred columns.
+    pub fn from_csv_infer<R>(mut reader: R) -> Result<Self, QueryError>
+    where R: Read {
+        let mut text = String::new();
+        reader
+            .read_to_string(&mut text)
+            .map_err(|_| QueryError::DataMismatch)?;
+
+        let mut records = parse_csv(&text)?.into_iter();
+        let mut db = Self::default();
+
+        let Some(names) = records.next() else {
+            return Ok(db);
+        };
+        let records: Vec<_> = records.collect();
+
+        if let Some(i) = records.iter().position(|record| record.len() != names.len()) {
+            return Err(QueryError::BatchFailed(i as Index, Box::new(QueryError::DataMismatch)));
+        }
+
+        let types = (0..names.len()).map(|col| {
+            records
+                .iter()
+                .take(CSV_INFER_ROWS)
+                .filter_map(|record| record.get(col).filter(|field| !field.is_empty()))
+                .map(|field| infer_type(field))
+                .reduce(widen_type)
+                .unwrap_or(Type::String)
+        });
+
+        db.append_columns(names.iter().cloned().zip(types).collect())?;
+
+        let rows: Vec<Row> = records
+            .into_iter()
+            .map(|record| {
+                record
+                    .into_iter()
+                    .zip(&db.columns)
+                    .map(|(field, col)| parse_field(field, &col.ty_restriction))
+                    .collect()
+            })
+            .collect();
+
+        db.append_rows_from_iter(rows.into_iter())?;
+        Ok(db)
+    }
+
+    /// Reads only the format version from the header of the file at the given path.
+    ///
+    /// The version is returned even if it is not supported by this version of the crate. Returns an
+    /// [Err] if the file is not a database.
+    pub fn read_version<P>(path: P) -> Result<u8, LoadDbError>
+    where P: AsRef<Path> {
+        Ok(read_header(&mut File::open(path)?)?)
+    }
+    
+    /// Constructs a new Db, checking the columns and rows like [Db::validate].
+    pub fn try_new(columns: Vec<Column>, rows: Vec<Row>) -> Result<Self, QueryError> {
+        let db = Self::new(columns, rows);
+        db.validate()?;
+        Ok(db)
+    }
+
+    /// Constructs a new Db, without checking that the rows match the columns.
+    pub const fn new(columns: Vec<Column>, rows: Vec<Row>) -> Self {
+        Self {
+            columns,
+            rows,
+            primary_key: None,
+            indexes: Vec::new(),
+            read_only: false,
+            autopad: false,
+            reject_duplicate_rows: false,
+            coerce_on_insert: false,
+            validate_on_write: false,
+            auto_index: false,
+            reject_non_finite: false,
+            observers: Vec::new(),
+            changes: 0,
+        }
+    }
+
+    /// Resolves the column identified by the given id into its index.
+    fn resolve_column(&self, id: &Id) -> Result<Index, QueryError> {
+        match id {
+            Id::Name(name) => self
+                .columns
+                .iter()
+                .position(|col| col.name == *name)
+                .map(|i| i as Index)
+                .ok_or(QueryError::NotFound),
+            Id::Index(i) => self.check_column(*i).map(|_| *i),
+        }
+    }
+
+    /// Checks that the row matches the arity and type restrictions of the columns, and may be
+    /// inserted as a new row.
+    fn check_row(&self, data: &Row) -> Result<(), QueryError> {
+        if data.len() != self.expected_row_len() {
+            return Err(QueryError::DataMismatch);
+        }
+        for (i, val) in data.iter().enumerate() {
+            self.check_cell(&self.columns[i], val)?;
+        }
+        if let Some(pk) = self.primary_key {
+            self.check_key(&data[pk as usize], None)?;
+        }
+        if self.reject_duplicate_rows && self.find_row(data).is_some() {
+            return Err(QueryError::DuplicateRow);
+        }
+        Ok(())
+    }
+
+    /// Checks the column and the value backfilled into the existing rows, then appends them,
+    /// returning the index of the column.
+    fn add_column(&mut self, col: Column, backfill: Data) -> Result<Index, QueryError> {
+        self.check_writable()?;
+        self.check_new_name(&col.name)?;
+        next_index(self.columns.len())?;
+        self.check_cell(&col, &backfill)?;
+        Ok(self.push_column(col, vec![backfill; self.rows.len()]))
+    }
+
+    /// Appends an already validated column along with one value per row, returning its index.
+    fn push_column(&mut self, col: Column, values: Vec<Data>) -> Index {
+        let index = self.columns.len() as Index;
+        self.columns.push(col);
+
+        for (row, val) in self.rows.iter_mut().zip(values) {
+            row.push(val);
+        }
+        self.column_added(index);
+        index
+    }
+
+    /// Removes the column at the given, already validated index along with its values, updating the
+    /// indexes and primary key.
+    fn remove_column(&mut self, index: Index) -> (Column, Vec<Data>) {
+        let col = self.columns.remove(index as usize);
+        let values = self.rows.iter_mut().map(|row| row.remove(index as usize)).collect();
+        self.indexes.retain(|idx| idx.column != index);
+
+        for idx in &mut self.indexes {
+            if idx.column > index {
+                idx.column -= 1;
+            }
+        }
+        self.primary_key = match self.primary_key {
+            Some(pk) if pk == index => None,
+            Some(pk) if pk > index => Some(pk - 1),
+            pk => pk,
+        };
+        self.emit(ChangeEvent::ColumnDropped(index));
+        (col, values)
+    }
+
+    /// Converts each value of the row to the type of its column, where this is lossless.
+    fn coerce_row(&self, data: &mut Row) {
+        for (val, col) in data.iter_mut().zip(&self.columns) {
+            if let Some(coerced) = val.coerce_to(&col.ty_restriction) {
+                *val = coerced;
+            }
+        }
+    }
+
+    /// Checks that the value satisfies the restrictions of the column, and is finite if non-finite
+    /// values are rejected.
+    fn check_cell(&self, column: &Column, val: &Data) -> Result<(), QueryError> {
+        check_value(column, val)?;
+        self.check_finite(val)
+    }
+
+    /// Checks that the value is finite, if non-finite values are rejected.
+    fn check_finite(&self, val: &Data) -> Result<(), QueryError> {
+        let finite = match val {
+            Data::Float(v) => v.is_finite(),
+            Data::Double(v) => v.is_finite(),
+            _ => true,
+        };
+        if self.reject_non_finite && !finite {
+            return Err(QueryError::NonFinite);
+        }
+        Ok(())
+    }
+
+    /// Checks that the value may be stored in the primary key column of the given row, or of a new
+    /// row if [None].
+    fn check_key(&self, key: &Data, row: Option<Index>) -> Result<(), QueryError> {
+        if *key == Data::Null {
+            return Err(QueryError::NullConstraintViolation);
+        }
+        match self.find_by_key(key) {
+            Some(existing) if Some(existing) != row => Err(QueryError::DuplicateKey),
+            _ => Ok(()),
+        }
+    }
+
+    /// Appends an already validated row, updating the indexes.
+    ///
+    /// Returns the index of the new row.
+    fn push_row(&mut self, data: Row) -> Result<Index, QueryError> {
+        let row = self.store_row(data)?;
+        self.emit(ChangeEvent::RowInserted(row));
+        Ok(row)
+    }
+
+    /// Appends an already validated row like [Db::push_row], without notifying the observers.
+    fn store_row(&mut self, data: Row) -> Result<Index, QueryError> {
+        let row = next_index(self.rows.len())?;
+
+        for idx in &mut self.indexes {
+            idx.insert(&data[idx.column as usize], row);
+        }
+        self.rows.push(data);
+        Ok(row)
+    }
+
+    /// Indexes the newly appended column if auto-indexing is enabled, then notifies the observers.
+    fn column_added(&mut self, index: Index) {
+        if self.auto_index {
+            self.indexes.push(HashIndex::build(index, &self.rows));
+        }
+        self.emit(ChangeEvent::ColumnAdded(index));
+    }
+
+    /// Notifies every observer of the event.
+    ///
+    /// Events are also counted, so an [UndoToken] can tell whether the database changed since it
+    /// was created.
+    fn emit(&mut self, event: ChangeEvent) {
+        self.changes = self.changes.wrapping_add(1);
+
+        for observer in &mut self.observers {
+            observer(&event);
+        }
+    }
+
+    /// Creates a token for the operation, stamped with the current amount of changes.
+    fn undo_token(&self, op: UndoOp) -> UndoToken {
+        UndoToken {
+            op,
+            changes: self.changes,
+        }
+    }
+
+    /// Notifies every observer that the rows were deleted, in the given order.
+    fn emit_rows_deleted<I>(&mut self, rows: I)
+    where I: IntoIterator<Item = Index> {
+        if self.observers.is_empty() {
+            self.changes = self.changes.wrapping_add(1);
+            return;
+        }
+        for row in rows {
+            self.emit(ChangeEvent::RowDeleted(row));
+        }
+    }
+
+    /// Rebuilds every index from the current rows.
+    fn rebuild_indexes(&mut self) {
+        for idx in &mut self.indexes {
+            *idx = HashIndex::build(idx.column, &self.rows);
+        }
+    }
+
+    /// Groups the indices of the rows whose values in the given columns are equal, in order of first
+    /// occurrence.
+    fn group_rows(&self, cols: &[usize]) -> Vec<Vec<Index>> {
+        let mut groups: Vec<Vec<Index>> = Vec::new();
+        let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+
+        for (i, row) in self.rows.iter().enumerate() {
+            let mut hasher = DefaultHasher::new();
+
+            for &col in cols {
+                row[col].hash(&mut hasher);
+            }
+            let bucket = buckets.entry(hasher.finish()).or_default();
+
+            let group = bucket.iter().copied().find(|&g| {
+                let first = &self.rows[groups[g][0] as usize];
+                cols.iter().all(|&col| first[col] == row[col])
+            });
+
+            match group {
+                Some(g) => groups[g].push(i as Index),
+                None => {
+                    bucket.push(groups.len());
+                    groups.push(vec![i as Index]);
+                }
+            }
+        }
+        groups
+    }
+
+    /// Validates the column index and value type of each condition, converting the indices into
+    /// [usize]s.
+    fn check_conditions(
+        &self,
+        conditions: Vec<(Index, Comparison, Data)>,
+    ) -> Result<Vec<(usize, Comparison, Data)>, QueryError> {
+        conditions
+            .into_iter()
+            .map(|(col, cmp, val)| {
+                let col = self.check_column(col)?;
+                check_type(&self.columns[col].ty_restriction, &val)?;
+                Ok((col, cmp, val))
+            })
+            .collect()
+    }
+
+    /// Returns the given column indices as [usize]s, or an [Err] if any are out of bounds.
+    fn check_columns(&self, indices: &[Index]) -> Result<Vec<usize>, QueryError> {
+        indices.iter().map(|&i| self.check_column(i)).collect()
+    }
+
+    /// Checks that the name is valid and not used by any column.
+    fn check_new_name(&self, name: &str) -> Result<(), QueryError> {
+        check_name(name)?;
+
+        if self.columns.iter().any(|col| col.name == name) {
+            return Err(QueryError::DuplicateName(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Returns an [Err] if the database is read-only.
+    fn check_writable(&self) -> Result<(), QueryError> {
+        if self.read_only {
+            return Err(QueryError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Returns the given column index as a [usize], or an [Err] if it is out of bounds.
+    fn check_column(&self, index: Index) -> Result<usize, QueryError> {
+        if (index as usize) < self.columns.len() {
+            return Ok(index as usize);
+        }
+        Err(QueryError::IndexOutOfBounds)
+    }
+}
+
+impl PartialEq for Db {
+    /// Compares the columns, rows and primary key, ignoring indexes and settings.
+    fn eq(&self, other: &Self) -> bool {
+        self.columns == other.columns
+            && self.rows == other.rows
+            && self.primary_key == other.primary_key
+    }
+}
+
+impl SerializeIo for Db {
+    /// Serializes the header, followed by the columns, the rows and the primary key.
+    ///
+    /// Fails without writing anything if validation on write is enabled and the database is
+    /// invalid.
+    fn serialize<W>(&self, w: &mut W) -> io::Result<()>
+    where W: Write {
+        if self.validate_on_write {
+            self.validate()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        w.writes(&MAGIC)?;
+        w.writes(&FORMAT_VERSION)?;
+        w.writes(&self.columns)?;
+        w.writes(&self.rows)?;
+        w.writes(&self.primary_key)
+    }
+}
+
+impl FromReader for Db {
+    /// Deserializes the header, followed by the columns, the rows and the primary key.
+    ///
+    /// Declared lengths are not trusted for preallocation, so a malformed length fails once the
+    /// reader runs out of bytes instead of exhausting memory. The decoded database is checked with
+    /// [Db::validate] before being returned.
+    fn from_reader<R>(r: &mut R) -> io::Result<Self>
+    where R: Read {
+        read_db(r, usize::MAX)
+    }
+}
+
+/// A small, seeded pseudorandom number generator.
+struct XorShift64 {
+    state: u64,
+}
+
+impl XorShift64 {
+    /// Constructs a generator from the seed, which may be any value.
+    fn new(seed: u64) -> Self {
+        Self {
+            state: (seed ^ 0x9E37_79B9_7F4A_7C15).max(1),
+        }
+    }
+
+    /// Returns the next pseudorandom number.
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+/// A 64-bit FNV-1a hasher whose output does not depend on the platform or Rust version.
+///
+/// Values are fed in explicitly rather than through [Hash], as the bytes produced by the standard
+/// library's impls are not guaranteed. Integers are hashed as little-endian bytes, lengths as
+/// [u64]s, and enum variants by a fixed tag byte.
+struct Fnv1a {
+    state: u64,
+}
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Self {
+            state: Self::OFFSET_BASIS,
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.state ^= byte as u64;
+            self.state = self.state.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write(&(len as u64).to_le_bytes());
+    }
+
+    fn write_str(&mut self, s: &str) {
+        self.write_len(s.len());
+        self.write(s.as_bytes());
+    }
+
+    fn write_type(&mut self, ty: &Type) {
+        let tag: u8 = match ty {
+            Type::Int => 0,
+            Type::Long => 1,
+            Type::Float => 2,
+            Type::Double => 3,
+            Type::String => 4,
+        };
+        self.write(&[tag]);
+    }
+
+    /// Hashes the value, treating `0.0` and `-0.0` as equal like [Data]'s [Hash] impl.
+    fn write_data(&mut self, data: &Data) {
+        match data {
+            Data::Int(v) => {
+                self.write(&[0]);
+                self.write(&v.to_le_bytes());
+            }
+            Data::Long(v) => {
+                self.write(&[1]);
+                self.write(&v.to_le_bytes());
+            }
+            Data::Float(v) => {
+                self.write(&[2]);
+                self.write(&(if *v == 0.0 { 0 } else { v.to_bits() }).to_le_bytes());
+            }
+            Data::Double(v) => {
+                self.write(&[3]);
+                self.write(&(if *v == 0.0 { 0 } else { v.to_bits() }).to_le_bytes());
+            }
+            Data::String(v) => {
+                self.write(&[4]);
+                self.write_str(v);
+            }
+            Data::Null => self.write(&[5]),
+        }
+    }
+
+    fn write_row(&mut self, row: &Row) {
+        self.write_len(row.len());
+
+        for val in row {
+            self.write_data(val);
+        }
+    }
+
+    fn write_column(&mut self, col: &Column) {
+        self.write_str(&col.name);
+        self.write_type(&col.ty_restriction);
+
+        match col.max_len {
+            Some(max_len) => {
+                self.write(&[1]);
+                self.write(&max_len.to_le_bytes());
+            }
+            None => self.write(&[0]),
+        }
+        self.write_data(&col.default);
+        self.write(&[col.nullable as u8]);
+        self.write_len(col.metadata.len());
+
+        for (key, value) in &col.metadata {
+            self.write_str(key);
+            self.write_str(value);
+        }
+    }
+}
+
+/// Deserializes a database from a reader holding at most `budget` bytes.
+///
+/// Returns [io::ErrorKind::InvalidData] if a declared length could not fit in the remaining bytes,
+/// or the decoded database fails [Db::validate].
+fn read_db<R>(r: &mut R, budget: usize) -> io::Result<Db>
+where R: Read {
+    let version = read_header(r)?;
+
+    if version != FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported format version {version}"),
+        ));
+    }
+    let mut r = BoundedReader {
+        inner: r,
+        remaining: budget,
+    };
+    let columns = r.read_vec(COLUMN_MIN_SIZE, read_column)?;
+    let rows = r.read_vec(LEN_SIZE, |r| r.read_vec(TAG_SIZE, |r| r.reads()))?;
+
+    let mut db = Db::new(columns, rows);
+    db.primary_key = r.reads()?;
+
+    db.validate()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(db)
+}
+
+/// Deserializes a column, bounding the length of its metadata.
+fn read_column<R>(r: &mut BoundedReader<R>) -> io::Result<Column>
+where R: Read {
+    Ok(Column {
+        name: r.reads()?,
+        ty_restriction: r.reads()?,
+        max_len: r.reads()?,
+        default: r.reads()?,
+        nullable: r.reads()?,
+        metadata: r.read_vec(2, |r| r.reads())?,
+    })
+}
+
+/// Reads the header, returning the format version.
+///
+/// Returns [io::ErrorKind::InvalidData] if the magic bytes do not match.
+fn read_header<R>(r: &mut R) -> io::Result<u8>
+where R: Read {
+    if r.reads::<[u8; 4]>()? != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a database file"));
+    }
+    r.reads()
+}
+
+/// The bytes every serialized database starts with.
+const MAGIC: [u8; 4] = *b"TRDB";
+
+/// The version of the serialized format, written after the [MAGIC].
+pub const FORMAT_VERSION: u8 = 4;
+
+/// A reader which tracks the amount of bytes that may remain in its inner reader.
+struct BoundedReader<'a, R> {
+    inner: &'a mut R,
+    remaining: usize,
+}
+
+impl<R> BoundedReader<'_, R>
+where R: Read
+{
+    /// Reads a [u32] length followed by that many items, each occupying at least `min_size` bytes.
+    fn read_vec<T, F>(&mut self, min_size: usize, mut read: F) -> io::Result<Vec<T>>
+    where F: FnMut(&mut Self) -> io::Result<T> {
+        let len = self.reads::<u32>()? as usize;
+
+        if len.saturating_mul(min_size) > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Declared length exceeds the remaining input",
+            ));
+        }
+        let mut items = Vec::with_capacity(len.min(MAX_PREALLOC));
+
+        for _ in 0..len {
+            items.push(read(self)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<R> Read for BoundedReader<'_, R>
+where R: Read
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.remaining = self.remaining.saturating_sub(n);
+        Ok(n)
+    }
+}
+
+/// The maximum amount of items preallocated from a declared length.
+const MAX_PREALLOC: usize = 1024;
+
+/// The size of the smallest serialized column: an empty name, the type, no length limit, a `NULL`
+/// default and the nullability.
+const COLUMN_MIN_SIZE: usize = 1 + TAG_SIZE + 1 + TAG_SIZE + 1 + LEN_SIZE;
+
+/// The size of a serialized enum variant id.
+const TAG_SIZE: usize = 1;
+
+/// The size of a serialized collection length.
+const LEN_SIZE: usize = 4;
+
+/// Returns the size of the value once serialized.
+fn data_size(data: &Data) -> usize {
+    TAG_SIZE
+        + match data {
+            Data::Int(_) | Data::Float(_) => 4,
+            Data::Long(_) | Data::Double(_) => 8,
+            Data::String(s) => s.len() + 1,
+            Data::Null => 0,
+        }
+}
+
+/// Returns the amount of heap memory owned by the value, in bytes.
+fn data_heap_size(data: &Data) -> usize {
+    match data {
+        Data::String(s) => s.capacity(),
+        _ => 0,
+    }
+}
+
+/// Counts the occurrences of each distinct value, in order of first occurrence.
+fn count_distinct<'a, I>(values: I) -> Vec<(&'a Data, usize)>
+where I: IntoIterator<Item = &'a Data> {
+    let mut counts: Vec<(&Data, usize)> = Vec::new();
+    let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+
+    for val in values {
+        let bucket = buckets.entry(hash_data(val)).or_default();
+
+        match bucket.iter().find(|&&i| counts[i].0 == val) {
+            Some(&i) => counts[i].1 += 1,
+            None => {
+                bucket.push(counts.len());
+                counts.push((val, 1));
+            }
+        }
+    }
+    counts
+}
+
+/// Hashes the value with the default hasher.
+fn hash_data(data: &Data) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Converts the value at the given position of the row into [T].
+///
+/// Intended for implementing `TryFrom<&Row>` for use with [Db::rows_as]. Returns an [Err] if the
+/// position is out of bounds or the value is of a different type.
+pub fn row_value<T>(row: &Row, index: usize) -> Result<T, QueryError>
+where T: for<'a> TryFrom<&'a Data, Error = QueryError> {
+    row.get(index)
+        .ok_or(QueryError::DataOutOfBounds)
+        .and_then(T::try_from)
+}
+
+/// The extension of database files.
+pub const FILE_EXTENSION: &str = "tdb";
+
+/// Returns the path with the [FILE_EXTENSION] appended if it has no extension.
+fn with_default_extension(path: &Path) -> PathBuf {
+    if path.extension().is_some() {
+        return path.to_path_buf();
+    }
+    path.with_extension(FILE_EXTENSION)
+}
+
+/// The maximum amount of rows or columns, as their amount is serialized as a [u32].
+#[cfg(not(test))]
+const MAX_LEN: usize = u32::MAX as usize;
+/// A small limit, so tests can reach it.
+#[cfg(test)]
+const MAX_LEN: usize = 64;
+
+/// Returns the index of an item appended to a collection of the given length.
+///
+/// Returns an [Err] if the collection is already at [MAX_LEN].
+fn next_index(len: usize) -> Result<Index, QueryError> {
+    if len >= MAX_LEN {
+        return Err(QueryError::LimitExceeded);
+    }
+    Ok(len as Index)
+}
+
+/// Checks that the given data satisfies the type restriction.
+///
+/// `NULL` satisfies every type restriction, as nullable columns are backfilled with it.
+fn check_type(restriction: &Type, data: &Data) -> Result<(), QueryError> {
+    if *data != Data::Null && *restriction != data.get_type() {
+        return Err(QueryError::TypeMismatch(restriction.clone(), data.get_type()));
+    }
+    Ok(())
+}
+
+/// Checks that the column name is not empty or whitespace.
+fn check_name(name: &str) -> Result<(), QueryError> {
+    if name.trim().is_empty() {
+        return Err(QueryError::InvalidName);
+    }
+    Ok(())
+}
+
+/// Checks that the given data satisfies the restrictions of the column.
+fn check_value(column: &Column, data: &Data) -> Result<(), QueryError> {
+    if !column.nullable && *data == Data::Null {
+        return Err(QueryError::NullConstraintViolation);
+    }
+    check_type(&column.ty_restriction, data)?;
+    check_len(column.max_len, data)
+}
+
+/// Checks that the given data is a string no longer than the maximum length, if any.
+fn check_len(max_len: Option<u32>, data: &Data) -> Result<(), QueryError> {
+    match (max_len, data) {
+        (Some(max), Data::String(s)) if s.len() > max as usize => Err(QueryError::LengthExceeded),
+        _ => Ok(()),
+    }
+}
+
+/// The amount of records sampled by [Db::from_csv_infer] to infer the column types.
+pub const CSV_INFER_ROWS: usize = 100;
+
+/// Splits comma-separated text into records of fields, unquoting quoted fields.
+///
+/// Returns an [Err] if a quoted field is never closed.
+fn parse_csv(text: &str) -> Result<Vec<Vec<String>>, QueryError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(QueryError::DataMismatch),
+                }
+            },
+            ',' => record.push(mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(mem::take(&mut field));
+                records.push(mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Returns the narrowest type which can hold the textual value.
+fn infer_type(field: &str) -> Type {
+    let field = field.trim();
+
+    if field.parse::<i32>().is_ok() {
+        Type::Int
+    } else if field.parse::<i64>().is_ok() {
+        Type::Long
+    } else if field.contains(|c: char| c.is_ascii_digit()) && field.parse::<f64>().is_ok() {
+        Type::Double
+    } else {
+        Type::String
+    }
+}
+
+/// Converts the textual value into the given type, leaving it as a string if it does not convert.
+///
+/// Empty fields are read as `NULL`.
+fn parse_field(field: String, ty: &Type) -> Data {
+    if field.is_empty() {
+        return Data::Null;
+    }
+    let val = Data::String(field);
+    val.coerce_to(ty).unwrap_or(val)
+}
+
+/// Returns the narrowest type which can hold the values of both types, as inferred by
+/// [infer_type].
+fn widen_type(a: Type, b: Type) -> Type {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (Type::String, _) | (_, Type::String) => Type::String,
+        (Type::Double, _) | (_, Type::Double) => Type::Double,
+        _ => Type::Long,
+    }
+}
+
+/// Returns the SQL type best matching the restrictions of the column.
+fn sql_type(col: &Column) -> String {
+    match (&col.ty_restriction, col.max_len) {
+        (Type::Int, _) => "INTEGER".to_string(),
+        (Type::Long, _) => "BIGINT".to_string(),
+        (Type::Float, _) => "REAL".to_string(),
+        (Type::Double, _) => "DOUBLE PRECISION".to_string(),
+        (Type::String, Some(max)) => format!("VARCHAR({max})"),
+        (Type::String, None) => "TEXT".to_string(),
+    }
+}
+
+/// Renders the value as an SQL literal.
+///
+/// SQL has no literals for infinite or NaN values, so those are rendered as `NULL`.
+fn sql_literal(data: &Data) -> String {
+    match data {
+        Data::String(s) => format!("'{}'", s.replace('\'', "''")),
+        Data::Float(v) if !v.is_finite() => "NULL".to_string(),
+        Data::Double(v) if !v.is_finite() => "NULL".to_string(),
+        _ => data.to_plain_string(),
+    }
+}
+
+/// Double-quotes the SQL identifier, escaping any quotes within it.
+fn quote_sql_ident(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Quotes the field if it contains the delimiter, a quote or a line break.
+fn quote_field(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
+    }
+    field.to_string()
+}
+
+/// The reason that the DB failed to load.
+#[derive(Display, Debug)]
+pub enum LoadDbErrorKind {
+    Malformed,
+    Io,
+}
+
+/// An error produced by the DB when loading itself from a byte source fails.
+#[derive(Display, Debug, Error)]
+#[display("{kind}: {message}")]
+pub struct LoadDbError {
+    message: String,
+    kind: LoadDbErrorKind,
+}
+
+impl LoadDbError {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub const fn kind(&self) -> &LoadDbErrorKind {
+        &self.kind
+    }
+
+    const fn new(message: String, kind: LoadDbErrorKind) -> Self {
+        Self { message, kind }
+    }
+}
+
+impl From<io::Error> for LoadDbError {
+    fn from(value: io::Error) -> Self {
+        if value.kind() == io::ErrorKind::InvalidData {
+            return Self::new(value.to_string(), LoadDbErrorKind::Malformed);
+        }
+        Self::new(value.to_string(), LoadDbErrorKind::Io)
+    }
+}
+
+impl TryFrom<&[u8]> for Db {
+    type Error = LoadDbError;
+
+    /// Returns [LoadDbErrorKind::Malformed] if a declared length exceeds the remaining bytes, or
+    /// the decoded database is invalid.
     fn try_from(mut value: &[u8]) -> Result<Self, Self::Error> {
-        Ok(value.reads()?)
+        let budget = value.len();
+        Ok(read_db(&mut value, budget)?)
+    }
+}
+
+impl TryFrom<&Path> for Db {
+    type Error = LoadDbError;
+
+    /// Reads the whole file at the given path, then loads it like `TryFrom<&[u8]>`.
+    fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        Self::try_from(fs::read(path)?.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn people() -> Db {
+        let mut db = Db::default();
+        db.append_column("Name".to_string(), Type::String).unwrap();
+        db.append_column("Age".to_string(), Type::Int).unwrap();
+        db.append_row(vec![Data::from("Ann"), Data::Int(31)]).unwrap();
+        db.append_row(vec![Data::from("Bob"), Data::Int(25)]).unwrap();
+        db.append_row(vec![Data::from("Cid"), Data::Null]).unwrap();
+        db
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        let mut hasher = Fnv1a::new();
+        assert_eq!(hasher.finish(), 0xcbf2_9ce4_8422_2325);
+
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn checksum_is_pinned() {
+        assert_eq!(Db::default().checksum(), 0x8820_1fb9_60ff_6465);
+        assert_eq!(people().checksum(), 0x17a5_273c_fbb7_1fb9);
+    }
+
+    #[test]
+    fn checksum_detects_changes() {
+        let mut db = people();
+        assert_eq!(db.checksum(), people().checksum());
+
+        db.append_row(vec![Data::from("Dee"), Data::Int(40)]).unwrap();
+        assert_ne!(db.checksum(), people().checksum());
+
+        let mut renamed = people();
+        renamed.rename_columns(vec![(1, "Years".to_string())]).unwrap();
+        assert_ne!(renamed.checksum(), people().checksum());
+    }
+
+    #[test]
+    fn add_column_with_values_fills_each_row() {
+        let mut db = people();
+        let values = vec![Data::Int(1), Data::Int(2), Data::Int(3)];
+
+        let response = db.add_column_with_values("Id".to_string(), Type::Int, values);
+        assert!(matches!(response, Ok(QueryResponse::Ok(2))));
+        assert_eq!(db.rows()[2][2], Data::Int(3));
+    }
+
+    #[test]
+    fn add_column_with_values_rejects_bad_values() {
+        let mut db = people();
+
+        let short = vec![Data::Int(1)];
+        let response = db.add_column_with_values("Id".to_string(), Type::Int, short);
+        assert!(matches!(response, Err(QueryError::DataMismatch)));
+
+        let mistyped = vec![Data::from("a"), Data::Int(2), Data::Int(3)];
+        let response = db.add_column_with_values("Id".to_string(), Type::Int, mistyped);
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+
+        assert_eq!(db.expected_row_len(), 2);
+    }
+
+    #[test]
+    fn error_codes_are_stable_and_distinct() {
+        let errors = [
+            QueryError::IndexOutOfBounds,
+            QueryError::DataOutOfBounds,
+            QueryError::NotFound,
+            QueryError::DataMismatch,
+            QueryError::TypeMismatch(Type::Int, Type::Long),
+            QueryError::LimitExceeded,
+            QueryError::BatchFailed(0, Box::new(QueryError::NotFound)),
+            QueryError::LengthExceeded,
+            QueryError::DuplicateName("A".to_string()),
+            QueryError::InvalidName,
+            QueryError::ReadOnly,
+            QueryError::NullConstraintViolation,
+            QueryError::DuplicateKey,
+            QueryError::DuplicateRow,
+            QueryError::NonFinite,
+            QueryError::RepeatedColumn(0),
+            QueryError::StaleUndo,
+        ];
+        let codes: Vec<_> = errors.iter().map(QueryError::code).collect();
+        assert_eq!(codes, (1..=17).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn is_empty_only_counts_rows() {
+        let mut db = Db::default();
+        assert!(db.is_empty());
+
+        db.append_column("A".to_string(), Type::Int).unwrap();
+        assert!(db.is_empty());
+        assert!(db.columns()[0].is_nullable());
+
+        db.append_row(vec![Data::Int(1)]).unwrap();
+        assert!(!db.is_empty());
+    }
+
+    #[test]
+    fn try_cast_column_reports_each_row() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::String).unwrap();
+
+        for v in ["1", "x", "3"] {
+            db.append_row(vec![Data::from(v)]).unwrap();
+        }
+        let report = db.try_cast_column(0, Type::Int).unwrap();
+        assert!(!report.is_success());
+        assert_eq!(report.converted(), [0, 2]);
+        assert_eq!(report.failed(), [(1, Data::from("x"))]);
+        assert_eq!(db.columns()[0].ty_restriction(), &Type::String);
+        assert_eq!(db.rows()[0][0], Data::from("1"));
+
+        db.delete_row_by_index(1).unwrap();
+        assert!(db.try_cast_column(0, Type::Int).unwrap().is_success());
+        assert_eq!(db.columns()[0].ty_restriction(), &Type::Int);
+        assert_eq!(db.rows()[1][0], Data::Int(3));
+    }
+
+    #[test]
+    fn try_cast_column_keeps_keys_unique() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::Double).unwrap();
+        db.append_row(vec![Data::Double(1.0)]).unwrap();
+        db.append_row(vec![Data::Double(-1.0)]).unwrap();
+        db.set_primary_key(0).unwrap();
+
+        assert!(db.try_cast_column(0, Type::Int).unwrap().is_success());
+        assert!(db.try_cast_column(0, Type::String).is_ok());
+        assert_eq!(db.rows()[1][0], Data::from("-1"));
+    }
+
+    #[test]
+    fn try_cast_column_converts_the_default() {
+        let mut db = people();
+        db.set_column_default(1, Data::Int(18)).unwrap();
+
+        assert!(db.try_cast_column(1, Type::Long).unwrap().is_success());
+        assert_eq!(db.columns()[1].default(), &Data::Long(18));
+
+        db.append_row_partial(vec![Data::from("Dee")]).unwrap();
+        assert_eq!(db.rows()[3][1], Data::Long(18));
+    }
+
+    #[test]
+    fn try_cast_column_fails_if_the_default_does_not_convert() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::String).unwrap();
+        db.set_column_default(0, Data::from("x")).unwrap();
+        db.append_row(vec![Data::from("1")]).unwrap();
+
+        let response = db.try_cast_column(0, Type::Int);
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+        assert_eq!(db.columns()[0].ty_restriction(), &Type::String);
+        assert_eq!(db.rows()[0][0], Data::from("1"));
+    }
+
+    #[test]
+    fn append_row_partial_fills_trailing_columns() {
+        let mut db = people();
+        db.append_row_partial(vec![Data::from("Dee")]).unwrap();
+        assert_eq!(db.last_row(), Some(&vec![Data::from("Dee"), Data::Null]));
+
+        db.append_row_partial(Vec::new()).unwrap();
+        assert_eq!(db.last_row(), Some(&vec![Data::Null, Data::Null]));
+
+        let long = vec![Data::from("Eve"), Data::Int(1), Data::Int(2)];
+        assert!(matches!(db.append_row_partial(long), Err(QueryError::DataMismatch)));
+        assert_eq!(db.rows().len(), 5);
+    }
+
+    #[test]
+    fn expected_row_len_matches_columns() {
+        let mut db = Db::default();
+        assert_eq!(db.expected_row_len(), 0);
+
+        db.append_column("A".to_string(), Type::Int).unwrap();
+        assert_eq!(db.expected_row_len(), 1);
+
+        let row = vec![Data::Int(1), Data::Int(2)];
+        assert!(matches!(db.append_row(row), Err(QueryError::DataMismatch)));
+    }
+
+    #[test]
+    fn select_columns_where_projects_matching_rows() {
+        let db = people();
+        let rows = db.select_columns_where(&[1, 0], 1, |v| *v != Data::Null).unwrap();
+
+        assert_eq!(rows, [
+            vec![Data::Int(31), Data::from("Ann")],
+            vec![Data::Int(25), Data::from("Bob")],
+        ]);
+    }
+
+    #[test]
+    fn select_columns_where_checks_every_index() {
+        let db = people();
+
+        let projected = db.select_columns_where(&[0, 2], 0, |_| true);
+        assert!(matches!(projected, Err(QueryError::IndexOutOfBounds)));
+
+        let filtered = db.select_columns_where(&[0], 2, |_| true);
+        assert!(matches!(filtered, Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn header_line_quotes_special_names() {
+        let mut db = people();
+        db.append_column("a,b".to_string(), Type::Int).unwrap();
+        db.append_column("say \"hi\"".to_string(), Type::Int).unwrap();
+
+        assert_eq!(db.header_line(','), "Name,Age,\"a,b\",\"say \"\"hi\"\"\"");
+        assert_eq!(db.header_line('\t'), "Name\tAge\ta,b\t\"say \"\"hi\"\"\"");
+        assert_eq!(Db::default().header_line(','), "");
+    }
+
+    #[test]
+    fn index_follows_row_changes() {
+        let mut db = people();
+        db.query(Instruction::CreateIndex(Id::Name("Age".to_string()))).unwrap();
+        assert!(db.has_index(1));
+        assert_eq!(db.lookup(1, &Data::Int(25)).unwrap(), [1]);
+
+        db.delete_row_by_index(0).unwrap();
+        assert_eq!(db.lookup(1, &Data::Int(25)).unwrap(), [0]);
+
+        db.append_row(vec![Data::from("Dee"), Data::Int(25)]).unwrap();
+        assert_eq!(db.lookup(1, &Data::Int(25)).unwrap(), [0, 2]);
+    }
+
+    #[test]
+    fn dropping_an_index_falls_back_to_scanning() {
+        let mut db = people();
+        db.create_index(1).unwrap();
+        db.query(Instruction::DropIndex(Id::Index(1))).unwrap();
+
+        assert!(!db.has_index(1));
+        assert_eq!(db.lookup(1, &Data::Null).unwrap(), [2]);
+        assert!(matches!(db.drop_index(1), Err(QueryError::NotFound)));
+        assert!(matches!(db.create_index(2), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn estimate_file_size_matches_written_size() {
+        let mut db = people();
+        db.append_column_string("Code".to_string(), Some(8)).unwrap();
+        db.append_column_inferred("Score".to_string(), Data::Double(0.5)).unwrap();
+        db.set_column_metadata(0, "unit".to_string(), "none".to_string()).unwrap();
+        db.set_primary_key(0).unwrap();
+
+        let mut written = Vec::new();
+        db.write_to_writer(&mut written).unwrap();
+        assert_eq!(db.estimate_file_size(), written.len());
+
+        let empty = Db::default();
+        assert_eq!(empty.estimate_file_size(), empty.to_tora_bytes().unwrap().len());
+    }
+
+    #[test]
+    fn fetch_value_by_name_resolves_the_column() {
+        let db = people();
+        let response = db.fetch_value_by_name(1, "Age").unwrap();
+        assert_eq!(response.as_single(), Some(&Data::Int(25)));
+
+        assert!(matches!(db.fetch_value_by_name(0, "Height"), Err(QueryError::NotFound)));
+        assert!(matches!(db.fetch_value_by_name(3, "Age"), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn fetch_value_checks_both_indices() {
+        let db = people();
+        assert!(matches!(db.fetch_value(2, 0), Err(QueryError::DataOutOfBounds)));
+        assert!(matches!(db.fetch_value(0, 3), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn column_histogram_sorts_by_descending_count() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::Int).unwrap();
+
+        for v in [Data::Int(1), Data::Int(2), Data::Null, Data::Int(2), Data::Null, Data::Int(2)] {
+            db.append_row(vec![v]).unwrap();
+        }
+        assert_eq!(db.column_histogram(0).unwrap(), [
+            (Data::Int(2), 3),
+            (Data::Null, 2),
+            (Data::Int(1), 1),
+        ]);
+        assert!(matches!(db.column_histogram(1), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    struct Person {
+        name: String,
+        age: i32,
+    }
+
+    impl TryFrom<&Row> for Person {
+        type Error = QueryError;
+
+        fn try_from(row: &Row) -> Result<Self, Self::Error> {
+            Ok(Self {
+                name: row_value(row, 0)?,
+                age: row_value(row, 1)?,
+            })
+        }
+    }
+
+    #[test]
+    fn rows_as_converts_positionally() {
+        let mut db = people();
+        db.delete_row_by_index(2).unwrap();
+
+        let persons: Vec<Person> = db.rows_as().unwrap();
+        assert_eq!(persons.len(), 2);
+        assert_eq!(persons[1].name, "Bob");
+        assert_eq!(persons[1].age, 25);
+    }
+
+    #[test]
+    fn rows_as_returns_the_first_failure() {
+        let converted = people().rows_as::<Person>();
+        assert!(matches!(converted, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+
+        let row = vec![Data::Int(1)];
+        assert!(matches!(row_value::<i32>(&row, 1), Err(QueryError::DataOutOfBounds)));
+    }
+
+    #[test]
+    fn next_index_stops_at_the_limit() {
+        assert_eq!(next_index(MAX_LEN - 1).unwrap(), (MAX_LEN - 1) as Index);
+        assert!(matches!(next_index(MAX_LEN), Err(QueryError::LimitExceeded)));
+    }
+
+    #[test]
+    fn row_appends_stop_at_the_limit() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::Int).unwrap();
+
+        for i in 0..MAX_LEN {
+            db.append_row(vec![Data::Int(i as i32)]).unwrap();
+        }
+        let row = vec![Data::Int(0)];
+        assert!(matches!(db.append_row(row.clone()), Err(QueryError::LimitExceeded)));
+        assert!(matches!(db.insert_row_at(0, row), Err(QueryError::LimitExceeded)));
+        assert_eq!(db.rows().len(), MAX_LEN);
+    }
+
+    #[test]
+    fn column_appends_stop_at_the_limit() {
+        let mut db = Db::default();
+        let defs = (1..MAX_LEN).map(|i| (format!("C{i}"), Type::Int)).collect();
+        db.append_columns(defs).unwrap();
+
+        let batch = vec![("A".to_string(), Type::Int), ("B".to_string(), Type::Int)];
+        assert!(matches!(db.append_columns(batch), Err(QueryError::LimitExceeded)));
+        assert_eq!(db.expected_row_len(), MAX_LEN - 1);
+
+        db.append_column("A".to_string(), Type::Int).unwrap();
+        let response = db.append_column("B".to_string(), Type::Int);
+        assert!(matches!(response, Err(QueryError::LimitExceeded)));
+        assert_eq!(db.expected_row_len(), MAX_LEN);
+    }
+
+    /// Returns a path in the temporary directory which is unique to this process.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tora-db-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn default_extension_is_only_added_when_missing() {
+        assert_eq!(with_default_extension(Path::new("data")), Path::new("data.tdb"));
+        assert_eq!(with_default_extension(Path::new("data.bin")), Path::new("data.bin"));
+    }
+
+    #[test]
+    fn auto_ext_files_round_trip() {
+        let path = temp_path("auto_ext");
+        let db = people();
+        db.write_to_file_auto_ext(&path).unwrap();
+
+        let file = path.with_extension(FILE_EXTENSION);
+        assert!(file.exists());
+        assert!(Db::read_from_file_auto_ext(&path).unwrap() == db);
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn swap_columns_moves_values_indexes_and_key() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+        db.create_index(1).unwrap();
+        db.swap_columns(0, 1).unwrap();
+
+        assert_eq!(db.header_line(','), "Age,Name");
+        assert_eq!(db.rows()[0], [Data::Int(31), Data::from("Ann")]);
+        assert_eq!(db.primary_key(), Some(1));
+        assert!(db.has_index(0) && !db.has_index(1));
+        assert_eq!(db.lookup(0, &Data::Int(25)).unwrap(), [1]);
+        assert!(matches!(db.swap_columns(0, 2), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    /// Returns the position and error of a [QueryError::BatchFailed].
+    fn batch_error<T>(result: Result<T, QueryError>) -> (Index, QueryError) {
+        match result {
+            Err(QueryError::BatchFailed(i, e)) => (i, *e),
+            _ => panic!("expected a batch failure"),
+        }
+    }
+
+    #[test]
+    fn append_rows_from_iter_stops_at_the_first_failure() {
+        let mut db = people();
+        let rows = vec![
+            vec![Data::from("Dee"), Data::Int(1)],
+            vec![Data::Int(2)],
+            vec![Data::from("Eve"), Data::Int(3)],
+        ];
+        let mut consumed = 0;
+        let iter = rows.into_iter().inspect(|_| consumed += 1);
+
+        let (i, e) = batch_error(db.append_rows_from_iter(iter));
+        assert_eq!(i, 1);
+        assert!(matches!(e, QueryError::DataMismatch));
+        assert_eq!(consumed, 2);
+        assert_eq!(db.rows().len(), 4);
+    }
+
+    #[test]
+    fn append_rows_from_iter_returns_the_new_indices() {
+        let mut db = people();
+        let rows = (0..2).map(|i| vec![Data::from("Dee"), Data::Int(i)]);
+
+        let response = db.append_rows_from_iter(rows).unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedRows(rows) if rows == [3, 4]));
+    }
+
+    #[test]
+    fn export_instructions_rebuild_an_equal_database() {
+        let mut db = people();
+        db.append_column_required("Id".to_string(), Type::Long, Data::Long(0)).unwrap();
+        db.fill_column_with_sequence(2, 1, 1).unwrap();
+        db.append_column_string("Code".to_string(), Some(4)).unwrap();
+        db.set_column_default(3, Data::from("none")).unwrap();
+        db.set_column_metadata(1, "unit".to_string(), "years".to_string()).unwrap();
+        db.set_primary_key(2).unwrap();
+
+        let mut rebuilt = Db::default();
+        rebuilt.apply_all(db.export_instructions()).unwrap();
+        assert!(rebuilt == db);
+        assert_eq!(rebuilt.checksum(), db.checksum());
+    }
+
+    #[test]
+    fn apply_all_stops_at_the_first_failure() {
+        let mut db = people();
+        let instructions = vec![
+            Instruction::DeleteRow(0),
+            Instruction::DeleteRow(5),
+            Instruction::DeleteRow(0),
+        ];
+        let (i, e) = batch_error(db.apply_all(instructions));
+        assert_eq!(i, 1);
+        assert!(matches!(e, QueryError::IndexOutOfBounds));
+        assert_eq!(db.rows().len(), 2);
+    }
+
+    #[test]
+    fn string_length_limit_counts_bytes() {
+        let mut db = Db::default();
+        db.append_column_string("Code".to_string(), Some(3)).unwrap();
+        assert_eq!(db.columns()[0].max_len(), Some(3));
+
+        db.append_row(vec![Data::from("abc")]).unwrap();
+        db.append_row(vec![Data::Null]).unwrap();
+
+        let long = vec![Data::from("abcd")];
+        assert!(matches!(db.append_row(long), Err(QueryError::LengthExceeded)));
+
+        let wide = vec![Data::from("ää")];
+        assert!(matches!(db.append_row(wide), Err(QueryError::LengthExceeded)));
+        assert_eq!(db.rows().len(), 2);
+    }
+
+    #[test]
+    fn count_where_matches_select_where() {
+        let db = people();
+        let adult = |v: &Data| matches!(v, Data::Int(age) if *age > 30);
+
+        assert_eq!(db.count_where(1, adult).unwrap(), 1);
+        assert_eq!(db.select_where(1, adult).unwrap(), [db.rows()[0].clone()]);
+        assert!(matches!(db.count_where(2, adult), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn numbers_are_serialized_little_endian() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::Long).unwrap();
+        db.append_row(vec![Data::Long(0x0102_0304_0506_0708)]).unwrap();
+
+        let mut expected = b"TRDB".to_vec();
+        expected.push(FORMAT_VERSION);
+        expected.extend([1, 0, 0, 0]);
+        expected.extend(b"V\0");
+        expected.extend([1, 0, 5, 1, 0, 0, 0, 0]);
+        expected.extend([1, 0, 0, 0, 1, 0, 0, 0]);
+        expected.extend([1, 8, 7, 6, 5, 4, 3, 2, 1]);
+        expected.push(0);
+
+        assert_eq!(db.to_tora_bytes().unwrap(), expected);
+    }
+
+    #[test]
+    fn append_column_inferred_uses_the_sample_type() {
+        let mut db = people();
+        db.append_column_inferred("Score".to_string(), Data::Double(1.5)).unwrap();
+
+        assert_eq!(db.columns()[2].ty_restriction(), &Type::Double);
+        assert_eq!(db.rows()[0][2], Data::Double(1.5));
+
+        let response = db.append_column_inferred("Other".to_string(), Data::Null);
+        assert!(matches!(response, Err(QueryError::DataMismatch)));
+    }
+
+    #[test]
+    fn response_accessors_match_their_variant() {
+        let rows = vec![vec![Data::Int(1)]];
+        assert_eq!(QueryResponse::Rows(rows.clone()).as_rows(), Some(rows.as_slice()));
+        assert_eq!(QueryResponse::Rows(rows).as_single(), None);
+
+        let single = QueryResponse::OkSingle(Data::Int(1));
+        assert_eq!(single.as_single(), Some(&Data::Int(1)));
+        assert_eq!(QueryResponse::Ok(0).as_rows(), None);
+    }
+
+    #[test]
+    fn compare_schema_matches_columns_by_name() {
+        let db = people();
+        let mut other = Db::default();
+        other.append_column("Age".to_string(), Type::Long).unwrap();
+        other.append_column("Email".to_string(), Type::String).unwrap();
+
+        let diff = db.compare_schema(&other);
+        assert_eq!(diff.only_in_self(), ["Name"]);
+        assert_eq!(diff.only_in_other(), ["Email"]);
+        assert_eq!(diff.type_changed(), [("Age".to_string(), Type::Int, Type::Long)]);
+        assert!(!diff.is_empty());
+
+        let mut swapped = people();
+        swapped.swap_columns(0, 1).unwrap();
+        assert!(db.compare_schema(&swapped).is_empty());
+    }
+
+    #[test]
+    fn column_defaults_fill_omitted_values() {
+        let mut db = people();
+        db.set_column_default(1, Data::Int(18)).unwrap();
+        assert_eq!(db.columns()[1].default(), &Data::Int(18));
+
+        db.append_row_partial(vec![Data::from("Dee")]).unwrap();
+        assert_eq!(db.rows()[3][1], Data::Int(18));
+        assert_eq!(db.rows()[2][1], Data::Null);
+
+        let response = db.set_column_default(1, Data::from("x"));
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+        assert!(matches!(db.set_column_default(2, Data::Null), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    /// Returns a header followed by the given bytes.
+    fn with_header(body: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.extend(body);
+        bytes
+    }
+
+    /// Asserts that loading the bytes fails with [LoadDbErrorKind::Malformed].
+    fn assert_malformed(bytes: &[u8]) {
+        match Db::try_from(bytes) {
+            Err(e) => assert!(matches!(e.kind(), LoadDbErrorKind::Malformed), "{e}"),
+            Ok(_) => panic!("expected the bytes to be rejected"),
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+
+        let loaded = Db::try_from(db.to_tora_bytes().unwrap().as_slice()).unwrap();
+        assert!(loaded == db);
+    }
+
+    #[test]
+    fn oversized_length_prefixes_are_malformed() {
+        let huge = u32::MAX.to_le_bytes();
+        assert_malformed(&with_header(&huge));
+
+        let rows = [[0; 4], huge].concat();
+        assert_malformed(&with_header(&rows));
+
+        let cells = [[0; 4], [1, 0, 0, 0], huge].concat();
+        assert_malformed(&with_header(&cells));
+    }
+
+    #[test]
+    fn rows_not_matching_the_columns_are_malformed() {
+        let missing = Db::new(vec![Column::new("A".to_string(), Type::Int)], vec![Vec::new()]);
+        assert_malformed(&missing.to_tora_bytes().unwrap());
+
+        let mistyped = Db::new(vec![Column::new("A".to_string(), Type::Int)], vec![vec![
+            Data::from("x"),
+        ]]);
+        assert_malformed(&mistyped.to_tora_bytes().unwrap());
+    }
+
+    #[test]
+    fn out_of_bounds_primary_key_is_malformed() {
+        let mut bytes = people().to_tora_bytes().unwrap();
+        bytes.pop();
+        bytes.extend([1, 9, 0, 0, 0]);
+        assert_malformed(&bytes);
+    }
+
+    #[test]
+    fn null_counts() {
+        let mut db = people();
+        db.append_column("Email".to_string(), Type::String).unwrap();
+
+        assert_eq!(db.column_null_count(1).unwrap(), 1);
+        assert_eq!(db.column_null_count(2).unwrap(), 3);
+        assert_eq!(db.total_null_count(), 4);
+        assert!(matches!(db.column_null_count(3), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn alter_column_renames_and_retypes() {
+        let mut db = people();
+        db.alter_column(1, Some("Years".to_string()), Some(Type::Long)).unwrap();
+
+        assert_eq!(db.header_line(','), "Name,Years");
+        assert_eq!(db.columns()[1].ty_restriction(), &Type::Long);
+        assert_eq!(db.rows()[0][1], Data::Long(31));
+    }
+
+    #[test]
+    fn alter_column_keeps_the_default_usable() {
+        let mut db = people();
+        db.set_column_default(1, Data::Int(18)).unwrap();
+        db.alter_column(1, None, Some(Type::Long)).unwrap();
+
+        db.append_row_partial(vec![Data::from("Dee")]).unwrap();
+        assert_eq!(db.rows()[3][1], Data::Long(18));
+    }
+
+    #[test]
+    fn failed_alter_column_changes_nothing() {
+        let mut db = people();
+
+        let response = db.alter_column(0, Some("Label".to_string()), Some(Type::Int));
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+
+        let response = db.alter_column(1, Some("Name".to_string()), Some(Type::Long));
+        assert!(matches!(response, Err(QueryError::DuplicateName(_))));
+        assert!(db == people());
+    }
+
+    #[test]
+    fn first_and_last_row() {
+        let db = people();
+        assert_eq!(db.first_row().unwrap()[0], Data::from("Ann"));
+        assert_eq!(db.last_row().unwrap()[0], Data::from("Cid"));
+        assert_eq!(Db::default().first_row(), None);
+        assert_eq!(Db::default().last_row(), None);
+    }
+
+    #[test]
+    fn take_rows_drains_rows_and_keeps_columns() {
+        let mut db = people();
+        db.create_index(1).unwrap();
+
+        let rows = db.take_rows().unwrap();
+        assert_eq!(rows, people().rows());
+        assert!(db.is_empty());
+        assert_eq!(db.expected_row_len(), 2);
+        assert!(db.has_index(1));
+
+        db.append_row(vec![Data::from("Dee"), Data::Int(25)]).unwrap();
+        assert_eq!(db.lookup(1, &Data::Int(25)).unwrap(), [0]);
+    }
+
+    #[test]
+    fn deduplicate_on_keeps_the_first_occurrence() {
+        let mut db = people();
+        db.append_row(vec![Data::from("Ann"), Data::Int(40)]).unwrap();
+        db.append_row(vec![Data::from("Bob"), Data::Int(25)]).unwrap();
+
+        let response = db.deduplicate_on(&[0]).unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedRows(rows) if rows == [3, 4]));
+        assert!(db == people());
+
+        assert!(matches!(db.deduplicate_on(&[2]), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn find_duplicates_groups_matching_rows() {
+        let mut db = people();
+        db.append_row(vec![Data::from("Ann"), Data::Int(40)]).unwrap();
+        db.append_row(vec![Data::from("Ann"), Data::Int(31)]).unwrap();
+
+        assert_eq!(db.find_duplicates(&[0]).unwrap(), [vec![0, 3, 4]]);
+        assert_eq!(db.find_duplicates(&[0, 1]).unwrap(), [vec![0, 4]]);
+        assert_eq!(db.find_duplicates(&[]).unwrap(), [vec![0, 1, 2, 3, 4]]);
+        assert!(people().find_duplicates(&[0]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn eq_unordered_ignores_column_order() {
+        let db = people();
+        let mut swapped = people();
+        swapped.swap_columns(0, 1).unwrap();
+
+        assert!(db.eq_unordered(&swapped));
+        assert!(db != swapped);
+
+        swapped.delete_row_by_index(2).unwrap();
+        assert!(!db.eq_unordered(&swapped));
+    }
+
+    #[test]
+    fn render_table_applies_display_options() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::Double).unwrap();
+        db.append_row(vec![Data::Double(1.23456)]).unwrap();
+        db.append_row(vec![Data::Null]).unwrap();
+
+        let options = DisplayOptions::default()
+            .with_float_precision(2)
+            .with_null_text("-".to_string());
+
+        assert_eq!(db.render_table(&options), "V\n----\n1.23\n-\n");
+        assert_eq!(db.render_table(&DisplayOptions::default()), "V\n-------\n1.23456\nNULL\n");
+    }
+
+    #[test]
+    fn blank_column_names_are_rejected() {
+        let mut db = Db::default();
+
+        for name in ["", "  "] {
+            let response = db.append_column(name.to_string(), Type::Int);
+            assert!(matches!(response, Err(QueryError::InvalidName)));
+        }
+        let defs = vec![("A".to_string(), Type::Int), ("\t".to_string(), Type::Int)];
+        assert!(matches!(db.append_columns(defs), Err(QueryError::InvalidName)));
+        assert_eq!(db.expected_row_len(), 0);
+    }
+
+    #[test]
+    fn row_to_string_pairs_names_and_values() {
+        let db = people();
+        assert_eq!(db.row_to_string(0).unwrap(), "Name=`Ann`str, Age=31int");
+        assert_eq!(db.row_to_string(2).unwrap(), "Name=`Cid`str, Age=NULL");
+        assert!(matches!(db.row_to_string(3), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn append_columns_validates_the_whole_batch() {
+        let mut db = people();
+
+        let repeated = vec![("A".to_string(), Type::Int), ("A".to_string(), Type::Long)];
+        let response = db.append_columns(repeated);
+        assert!(matches!(response, Err(QueryError::DuplicateName(name)) if name == "A"));
+
+        let taken = vec![("Age".to_string(), Type::Int)];
+        assert!(matches!(db.append_columns(taken), Err(QueryError::DuplicateName(_))));
+        assert_eq!(db.expected_row_len(), 2);
+
+        let defs = vec![("A".to_string(), Type::Int), ("B".to_string(), Type::String)];
+        db.append_columns(defs).unwrap();
+        assert_eq!(db.header_line(','), "Name,Age,A,B");
+        assert_eq!(db.rows()[0][3], Data::Null);
+    }
+
+    #[test]
+    fn fetch_range_clamps_the_end() {
+        let db = people();
+        assert_eq!(db.fetch_range(0, 1).unwrap(), [db.rows()[0].clone()]);
+        assert_eq!(db.fetch_range(1, 10).unwrap().len(), 2);
+        assert!(db.fetch_range(2, 1).unwrap().is_empty());
+        assert!(db.fetch_range(3, 3).unwrap().is_empty());
+        assert!(matches!(db.fetch_range(4, 5), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn read_only_rejects_mutations() {
+        let mut db = people();
+        db.set_read_only(true);
+        assert!(db.is_read_only());
+
+        let row = vec![Data::from("Dee"), Data::Int(1)];
+        assert!(matches!(db.append_row(row.clone()), Err(QueryError::ReadOnly)));
+        assert!(matches!(db.append_column("X".to_string(), Type::Int), Err(QueryError::ReadOnly)));
+        assert!(matches!(db.delete_row_by_index(0), Err(QueryError::ReadOnly)));
+        assert!(matches!(db.query(Instruction::Clear), Err(QueryError::ReadOnly)));
+        assert!(matches!(db.take_rows(), Err(QueryError::ReadOnly)));
+
+        assert!(db == people());
+        assert!(db.fetch_value(0, 0).is_ok());
+
+        db.set_read_only(false);
+        db.append_row(row).unwrap();
+    }
+
+    #[test]
+    fn autopad_pads_short_rows_only() {
+        let mut db = people();
+        let short = vec![Data::from("Dee")];
+        assert!(matches!(db.append_row(short.clone()), Err(QueryError::DataMismatch)));
+
+        db.set_autopad(true);
+        db.append_row(short).unwrap();
+        assert_eq!(db.rows()[3], [Data::from("Dee"), Data::Null]);
+
+        let long = vec![Data::from("Eve"), Data::Int(1), Data::Int(2)];
+        assert!(matches!(db.append_row(long), Err(QueryError::DataMismatch)));
+    }
+
+    #[test]
+    fn files_start_with_magic_and_version() {
+        let bytes = people().to_tora_bytes().unwrap();
+        assert_eq!(bytes[..4], MAGIC);
+        assert_eq!(bytes[4], FORMAT_VERSION);
+
+        let path = temp_path("version.tdb");
+        people().write_to_file(&path).unwrap();
+        assert_eq!(Db::read_version(&path).unwrap(), FORMAT_VERSION);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unknown_versions_and_foreign_files_are_malformed() {
+        let mut bytes = people().to_tora_bytes().unwrap();
+        bytes[4] = FORMAT_VERSION + 1;
+        assert_malformed(&bytes);
+
+        assert_malformed(b"PK\x03\x04 not a database");
+    }
+
+    #[test]
+    fn required_columns_reject_null() {
+        let mut db = people();
+        db.append_column_required("Active".to_string(), Type::Int, Data::Int(1)).unwrap();
+        assert!(!db.columns()[2].is_nullable());
+        assert_eq!(db.rows()[0][2], Data::Int(1));
+
+        let row = vec![Data::from("Dee"), Data::Int(1), Data::Null];
+        assert!(matches!(db.append_row(row), Err(QueryError::NullConstraintViolation)));
+
+        db.append_row_partial(vec![Data::from("Dee")]).unwrap();
+        assert_eq!(db.rows()[3][2], Data::Int(1));
+    }
+
+    #[test]
+    fn required_columns_need_a_default() {
+        let mut db = people();
+        let response = db.append_column_required("Active".to_string(), Type::Int, Data::Null);
+        assert!(matches!(response, Err(QueryError::NullConstraintViolation)));
+
+        let response = db.append_column_required("Active".to_string(), Type::Int, Data::Long(1));
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Int, Type::Long))));
+        assert_eq!(db.expected_row_len(), 2);
+    }
+
+    #[test]
+    fn to_tora_bytes_matches_write_to_writer() {
+        let db = people();
+        let bytes = db.to_tora_bytes().unwrap();
+
+        let mut written = Vec::new();
+        db.write_to_writer(&mut written).unwrap();
+        assert_eq!(bytes, written);
+        assert!(Db::try_from(bytes.as_slice()).unwrap() == db);
+    }
+
+    #[test]
+    fn column_cardinality_ignores_null() {
+        let mut db = people();
+        db.append_row(vec![Data::from("Ann"), Data::Null]).unwrap();
+
+        assert_eq!(db.column_cardinality(0).unwrap(), 3);
+        assert_eq!(db.column_cardinality(1).unwrap(), 2);
+        assert!(matches!(db.column_cardinality(2), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn truncate_keeps_columns_and_clear_removes_them() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+
+        db.query(Instruction::Truncate).unwrap();
+        assert!(db.is_empty());
+        assert_eq!(db.expected_row_len(), 2);
+        assert_eq!(db.primary_key(), Some(0));
+
+        db.query(Instruction::Clear).unwrap();
+        assert_eq!(db.expected_row_len(), 0);
+        assert_eq!(db.primary_key(), None);
+    }
+
+    #[test]
+    fn reserve_rows_grows_the_capacity() {
+        let mut db = people();
+        db.reserve_rows(100);
+        assert!(db.row_capacity() >= 103);
+    }
+
+    #[test]
+    fn schema_compatible_with_compares_order_and_types() {
+        let db = people();
+        assert!(db.schema_compatible_with(&people()));
+
+        let mut empty = people();
+        empty.truncate().unwrap();
+        assert!(db.schema_compatible_with(&empty));
+
+        let mut swapped = people();
+        swapped.swap_columns(0, 1).unwrap();
+        assert!(!db.schema_compatible_with(&swapped));
+
+        let mut retyped = people();
+        retyped.alter_column(1, None, Some(Type::Long)).unwrap();
+        assert!(!db.schema_compatible_with(&retyped));
+    }
+
+    #[test]
+    fn sort_by_columns_breaks_ties_with_later_keys() {
+        let mut db = people();
+        db.append_row(vec![Data::from("Ann"), Data::Int(20)]).unwrap();
+        db.create_index(1).unwrap();
+
+        let response = db.sort_by_columns(&[(0, false), (1, true)]).unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedRows(order) if order == [2, 1, 3, 0]));
+        assert_eq!(db.transpose()[1], [Data::Null, Data::Int(25), Data::Int(20), Data::Int(31)]);
+        assert_eq!(db.lookup(1, &Data::Int(20)).unwrap(), [2]);
+
+        db.sort_by_columns(&[(1, true)]).unwrap();
+        assert_eq!(db.transpose()[1], [Data::Null, Data::Int(20), Data::Int(25), Data::Int(31)]);
+    }
+
+    #[test]
+    fn sort_by_columns_checks_every_key() {
+        let mut db = people();
+        let response = db.sort_by_columns(&[(0, true), (2, true)]);
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+        assert!(db == people());
+    }
+
+    #[test]
+    fn upsert_by_key_updates_or_inserts() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+        let age = |v| HashMap::from([(1, Data::Int(v))]);
+
+        let response = db.upsert_by_key(Data::from("Bob"), age(26));
+        assert!(matches!(response, Ok(QueryResponse::Ok(1))));
+        assert_eq!(db.rows()[1][1], Data::Int(26));
+
+        let response = db.upsert_by_key(Data::from("Dee"), age(40));
+        assert!(matches!(response, Ok(QueryResponse::Ok(3))));
+        assert_eq!(db.find_by_key(&Data::from("Dee")), Some(3));
+        assert_eq!(db.rows()[3], [Data::from("Dee"), Data::Int(40)]);
+    }
+
+    #[test]
+    fn primary_keys_stay_unique_and_not_null() {
+        let mut db = people();
+        assert!(matches!(db.set_primary_key(1), Err(QueryError::NullConstraintViolation)));
+        db.set_primary_key(0).unwrap();
+
+        let rename = HashMap::from([(0, Data::from("Ann"))]);
+        let response = db.upsert_by_key(Data::from("Bob"), rename);
+        assert!(matches!(response, Err(QueryError::DuplicateKey)));
+
+        let row = vec![Data::from("Ann"), Data::Int(1)];
+        assert!(matches!(db.append_row(row), Err(QueryError::DuplicateKey)));
+        assert!(db == {
+            let mut db = people();
+            db.set_primary_key(0).unwrap();
+            db
+        });
+
+        db.clear_primary_key().unwrap();
+        let response = db.upsert_by_key(Data::from("Ann"), HashMap::new());
+        assert!(matches!(response, Err(QueryError::NotFound)));
+    }
+
+    /// Registers an observer on the database, returning the events it records.
+    fn record_events(db: &mut Db) -> Arc<Mutex<Vec<ChangeEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        db.on_change(Box::new(move |event| sink.lock().unwrap().push(event.clone())));
+        events
+    }
+
+    #[test]
+    fn observers_see_changes_in_order() {
+        let mut db = people();
+        let events = record_events(&mut db);
+
+        db.append_row(vec![Data::from("Dee"), Data::Int(1)]).unwrap();
+        db.swap_columns(0, 1).unwrap();
+        db.rename_columns(vec![(0, "Years".to_string())]).unwrap();
+        db.sort_by_columns(&[(0, true)]).unwrap();
+        db.delete_row_by_index(0).unwrap();
+        db.truncate().unwrap();
+
+        assert_eq!(*events.lock().unwrap(), [
+            ChangeEvent::RowInserted(3),
+            ChangeEvent::ColumnsSwapped(0, 1),
+            ChangeEvent::ColumnRenamed(0),
+            ChangeEvent::RowsReordered,
+            ChangeEvent::RowDeleted(0),
+            ChangeEvent::RowDeleted(2),
+            ChangeEvent::RowDeleted(1),
+            ChangeEvent::RowDeleted(0),
+        ]);
+    }
+
+    #[test]
+    fn failed_changes_are_not_observed() {
+        let mut db = people();
+        let events = record_events(&mut db);
+
+        assert!(db.append_row(vec![Data::Int(1)]).is_err());
+        assert!(db.set_column_default(1, Data::Int(18)).is_ok());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn db_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Db>();
+    }
+
+    #[test]
+    fn try_from_path_reads_the_whole_file() {
+        let path = temp_path("try_from.tdb");
+        people().write_to_file(&path).unwrap();
+        assert!(Db::try_from(path.as_path()).unwrap() == people());
+        fs::remove_file(&path).unwrap();
+
+        let missing = Db::try_from(temp_path("missing.tdb").as_path());
+        assert!(matches!(missing.err().unwrap().kind(), LoadDbErrorKind::Io));
+    }
+
+    #[test]
+    fn sample_is_seeded() {
+        let db = people();
+        assert_eq!(db.sample(2, 7), db.sample(2, 7));
+
+        let sample = db.sample(2, 7);
+        assert_eq!(sample.len(), 2);
+        assert_ne!(sample[0], sample[1]);
+    }
+
+    #[test]
+    fn oversized_sample_returns_every_row() {
+        let db = people();
+        let mut sample = db.sample(10, 1);
+        sample.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+        assert_eq!(sample, db.rows());
+        assert!(Db::default().sample(3, 0).is_empty());
+    }
+
+    #[test]
+    fn append_column_returning_index_matches_append_column_default() {
+        let mut db = people();
+        let index = db.append_column_returning_index("A".to_string(), Type::Int, Data::Int(0));
+        assert_eq!(index.unwrap(), 2);
+
+        let response = db.append_column_default("B".to_string(), Type::Int, Data::Int(0));
+        assert!(matches!(response, Ok(QueryResponse::Ok(3))));
+
+        let index = db.append_column_returning_index("C".to_string(), Type::Int, Data::from("x"));
+        assert!(matches!(index, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+        assert_eq!(db.expected_row_len(), 4);
+    }
+
+    #[test]
+    fn rows_where_indices_returns_matching_positions() {
+        let db = people();
+        assert_eq!(db.rows_where_indices(1, |v| *v == Data::Null).unwrap(), [2]);
+        assert_eq!(db.rows_where_indices(0, |_| true).unwrap(), [0, 1, 2]);
+
+        let response = db.rows_where_indices(2, |_| true);
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn prepend_row_shifts_existing_rows() {
+        let mut db = people();
+        db.create_index(1).unwrap();
+
+        let response = db.prepend_row(vec![Data::from("Zed"), Data::Int(25)]);
+        assert!(matches!(response, Ok(QueryResponse::Ok(0))));
+        assert_eq!(db.rows()[0][0], Data::from("Zed"));
+        assert_eq!(db.rows()[1][0], Data::from("Ann"));
+
+        let mut matches = db.lookup(1, &Data::Int(25)).unwrap();
+        matches.sort_unstable();
+        assert_eq!(matches, [0, 2]);
+    }
+
+    #[test]
+    fn insert_row_at_places_the_row() {
+        let mut db = people();
+        db.create_index(0).unwrap();
+        db.insert_row_at(1, vec![Data::from("Abe"), Data::Int(50)]).unwrap();
+
+        assert_eq!(db.transpose()[0], [
+            Data::from("Ann"),
+            Data::from("Abe"),
+            Data::from("Bob"),
+            Data::from("Cid"),
+        ]);
+        assert_eq!(db.lookup(0, &Data::from("Bob")).unwrap(), [2]);
+
+        db.insert_row_at(4, vec![Data::from("Dee"), Data::Int(1)]).unwrap();
+        assert_eq!(db.rows()[4][0], Data::from("Dee"));
+    }
+
+    #[test]
+    fn insert_row_at_rejects_bad_positions_and_rows() {
+        let mut db = people();
+        let row = vec![Data::from("Dee"), Data::Int(1)];
+
+        let response = db.insert_row_at(4, row);
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+
+        let response = db.insert_row_at(0, vec![Data::from("Dee")]);
+        assert!(matches!(response, Err(QueryError::DataMismatch)));
+        assert!(db == people());
+    }
+
+    #[test]
+    fn client_errors_are_classified() {
+        assert!(QueryError::TypeMismatch(Type::Int, Type::Long).is_client_error());
+        assert!(QueryError::DuplicateKey.is_client_error());
+        assert!(!QueryError::ReadOnly.is_client_error());
+        assert!(!QueryError::LimitExceeded.is_client_error());
+
+        let inner = |e| QueryError::BatchFailed(0, Box::new(e));
+        assert!(inner(QueryError::NotFound).is_client_error());
+        assert!(!inner(QueryError::ReadOnly).is_client_error());
+    }
+
+    #[test]
+    fn column_at_checks_bounds() {
+        let db = people();
+        let col = db.column_at(1).unwrap();
+
+        assert_eq!(col.name(), "Age");
+        assert_eq!(col.ty_restriction(), &Type::Int);
+        assert!(matches!(db.column_at(2), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn count_distinct_where_filters_then_counts() {
+        let mut db = people();
+        db.append_row(vec![Data::from("Ann"), Data::Int(50)]).unwrap();
+        db.append_row(vec![Data::Null, Data::Int(60)]).unwrap();
+        let older = |v: &Data| matches!(v, Data::Int(age) if *age >= 30);
+
+        assert_eq!(db.count_distinct_where(0, 1, older).unwrap(), 1);
+        assert_eq!(db.count_distinct_where(0, 1, |_| true).unwrap(), 3);
+        assert_eq!(db.count_distinct_where(1, 0, |v| *v == Data::Null).unwrap(), 1);
+
+        let response = db.count_distinct_where(0, 2, older);
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn rename_columns_allows_swapping_names() {
+        let mut db = people();
+        db.rename_columns(vec![(0, "Age".to_string()), (1, "Name".to_string())]).unwrap();
+        assert_eq!(db.header_line(','), "Age,Name");
+    }
+
+    #[test]
+    fn rename_columns_checks_the_final_names() {
+        let mut db = people();
+
+        let response = db.rename_columns(vec![(0, "Age".to_string())]);
+        assert!(matches!(response, Err(QueryError::DuplicateName(name)) if name == "Age"));
+
+        let response = db.rename_columns(vec![(0, "A".to_string()), (0, "B".to_string())]);
+        assert!(matches!(response, Err(QueryError::RepeatedColumn(0))));
+
+        let response = db.rename_columns(vec![(1, "A".to_string()), (0, " ".to_string())]);
+        assert!(matches!(response, Err(QueryError::InvalidName)));
+        assert_eq!(db.header_line(','), "Name,Age");
+    }
+
+    #[test]
+    fn append_row_unchecked_skips_validation() {
+        let mut db = people();
+        db.append_row_unchecked(vec![Data::Int(1), Data::from("x")]).unwrap();
+
+        assert_eq!(db.rows().len(), 4);
+        assert!(matches!(db.validate(), Err(QueryError::TypeMismatch(..))));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "row length mismatch")]
+    fn append_row_unchecked_asserts_the_length() {
+        let _ = people().append_row_unchecked(vec![Data::Int(1)]);
+    }
+
+    #[test]
+    fn column_index_map_maps_names_to_indices() {
+        let map = people().column_index_map();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["Name"], 0);
+        assert_eq!(map["Age"], 1);
+    }
+
+    #[test]
+    fn prune_columns_remaps_indexes_and_key() {
+        let mut db = people();
+        db.append_column("Tmp".to_string(), Type::Int).unwrap();
+        db.create_index(1).unwrap();
+        db.set_primary_key(0).unwrap();
+
+        let response = db.prune_columns(|col| col.name() != "Age").unwrap();
+        let QueryResponse::ModifiedColumns(removed) = response else {
+            panic!("expected the removed columns");
+        };
+        assert!(matches!(removed.as_slice(), [Id::Index(0), Id::Index(2)]));
+        assert_eq!(db.header_line(','), "Age");
+        assert_eq!(db.rows()[0], [Data::Int(31)]);
+        assert_eq!(db.primary_key(), None);
+        assert!(db.has_index(0));
+        assert_eq!(db.lookup(0, &Data::Int(25)).unwrap(), [1]);
+    }
+
+    #[test]
+    fn prune_columns_without_matches_changes_nothing() {
+        let mut db = people();
+        let response = db.prune_columns(|_| false).unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedColumns(ids) if ids.is_empty()));
+        assert!(db == people());
+    }
+
+    #[test]
+    fn duplicate_rows_can_be_rejected() {
+        let mut db = people();
+        let row = vec![Data::from("Ann"), Data::Int(31)];
+        db.append_row(row.clone()).unwrap();
+
+        db.set_reject_duplicate_rows(true);
+        assert!(matches!(db.append_row(row.clone()), Err(QueryError::DuplicateRow)));
+        assert!(matches!(db.validate_row(&row), Err(QueryError::DuplicateRow)));
+        assert_eq!(db.find_row(&row), Some(0));
+
+        db.append_row(vec![Data::from("Ann"), Data::Int(32)]).unwrap();
+        assert_eq!(db.rows().len(), 5);
+    }
+
+    #[test]
+    fn memory_footprint_counts_string_values() {
+        let mut db = people();
+        let before = db.memory_footprint();
+        assert!(before >= mem::size_of::<Db>());
+
+        db.append_row(vec![Data::from("x".repeat(1000)), Data::Int(1)]).unwrap();
+        assert!(db.memory_footprint() >= before + 1000);
+    }
+
+    #[test]
+    fn coerce_on_insert_converts_losslessly() {
+        let mut db = people();
+        let row = vec![Data::from("Dee"), Data::from("40")];
+        assert!(matches!(db.append_row(row.clone()), Err(QueryError::TypeMismatch(..))));
+
+        db.set_coerce_on_insert(true);
+        db.append_row(row).unwrap();
+        assert_eq!(db.rows()[3][1], Data::Int(40));
+
+        db.append_row(vec![Data::Int(7), Data::Long(41)]).unwrap();
+        assert_eq!(db.rows()[4], [Data::from("7"), Data::Int(41)]);
+
+        let row = vec![Data::from("Eve"), Data::Long(1 << 40)];
+        assert!(matches!(db.append_row(row), Err(QueryError::TypeMismatch(..))));
+    }
+
+    #[test]
+    fn extract_column_returns_definition_and_values() {
+        let mut db = people();
+        let (col, values) = db.extract_column(Id::Name("Name".to_string())).unwrap();
+
+        assert_eq!(col.name(), "Name");
+        assert_eq!(values, [Data::from("Ann"), Data::from("Bob"), Data::from("Cid")]);
+        assert_eq!(db.header_line(','), "Age");
+
+        let mut other = Db::default();
+        for _ in 0..3 {
+            other.append_default_row().unwrap();
+        }
+        let name = col.name().to_string();
+        other.add_column_with_values(name, col.ty_restriction().clone(), values).unwrap();
+        assert_eq!(other.rows()[1], [Data::from("Bob")]);
+    }
+
+    #[test]
+    fn deleting_a_missing_column_fails() {
+        let mut db = people();
+        let extracted = db.extract_column(Id::Name("Height".to_string()));
+        assert!(matches!(extracted, Err(QueryError::NotFound)));
+        assert!(matches!(db.delete_column_by_name("Height"), Err(QueryError::NotFound)));
+        assert!(matches!(db.delete_column_by_index(2), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn validate_catches_inconsistent_databases() {
+        assert!(people().validate().is_ok());
+        let col = || Column::new("A".to_string(), Type::Int);
+
+        let db = Db::new(vec![col(), col()], Vec::new());
+        assert!(matches!(db.validate(), Err(QueryError::DuplicateName(_))));
+
+        let db = Db::new(vec![col()], vec![Vec::new()]);
+        assert!(matches!(db.validate(), Err(QueryError::DataMismatch)));
+
+        let db = Db::new(vec![col()], vec![vec![Data::from("x")]]);
+        assert!(matches!(db.validate(), Err(QueryError::TypeMismatch(..))));
+
+        let mut db = Db::new(vec![col()], vec![vec![Data::Int(1)], vec![Data::Int(1)]]);
+        db.primary_key = Some(0);
+        assert!(matches!(db.validate(), Err(QueryError::DuplicateKey)));
+    }
+
+    #[test]
+    fn operations_keep_column_names_unique() {
+        let mut db = people();
+        let taken = || "Age".to_string();
+
+        assert!(matches!(db.append_column(taken(), Type::Int), Err(QueryError::DuplicateName(_))));
+        let response = db.append_column_required(taken(), Type::Int, Data::Int(0));
+        assert!(matches!(response, Err(QueryError::DuplicateName(_))));
+        let response = db.append_column_string(taken(), None);
+        assert!(matches!(response, Err(QueryError::DuplicateName(_))));
+        let response = db.append_column_def(Column::new(taken(), Type::Int));
+        assert!(matches!(response, Err(QueryError::DuplicateName(_))));
+        assert!(db.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_on_write_refuses_invalid_databases() {
+        let mut db = Db::new(vec![Column::new("A".to_string(), Type::Int)], vec![vec![
+            Data::from("x"),
+        ]]);
+        assert!(db.to_tora_bytes().is_ok());
+
+        db.set_validate_on_write(true);
+        let err = db.to_tora_bytes().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut valid = people();
+        valid.set_validate_on_write(true);
+        assert!(valid.to_tora_bytes().is_ok());
+    }
+
+    #[test]
+    fn try_new_validates_the_rows() {
+        let columns = || vec![Column::new("A".to_string(), Type::Int)];
+
+        let db = Db::try_new(columns(), vec![vec![Data::Int(1)]]).unwrap();
+        assert_eq!(db.rows().len(), 1);
+
+        let db = Db::try_new(columns(), vec![vec![Data::Int(1), Data::Int(2)]]);
+        assert!(matches!(db, Err(QueryError::DataMismatch)));
+    }
+
+    #[test]
+    fn auto_index_covers_new_columns_only() {
+        let mut db = people();
+        db.set_auto_index(true);
+        db.append_column("Team".to_string(), Type::String).unwrap();
+        db.append_columns(vec![("Level".to_string(), Type::Int)]).unwrap();
+
+        assert!(!db.has_index(0) && !db.has_index(1));
+        assert!(db.has_index(2) && db.has_index(3));
+        assert_eq!(db.lookup(2, &Data::Null).unwrap(), [0, 1, 2]);
+
+        let row = vec![Data::from("Dee"), Data::Int(1), Data::from("Red"), Data::Null];
+        db.append_row(row).unwrap();
+        assert_eq!(db.lookup(2, &Data::from("Red")).unwrap(), [3]);
+    }
+
+    #[test]
+    fn cursor_pages_through_rows() {
+        let db = people();
+        let mut cursor = db.cursor(2);
+
+        assert_eq!(cursor.next_page().unwrap(), db.rows()[..2]);
+        assert!(cursor.has_next());
+        assert_eq!(cursor.next_page().unwrap(), db.rows()[2..]);
+        assert!(!cursor.has_next());
+        assert_eq!(cursor.next_page(), None);
+
+        assert_eq!(db.cursor(0).next_page().unwrap().len(), 1);
+        assert_eq!(Db::default().cursor(5).next_page(), None);
+    }
+
+    #[test]
+    fn append_row_returning_key_returns_the_key() {
+        let mut db = people();
+        let response = db.append_row_returning_key(vec![Data::from("Dee"), Data::Int(1)]);
+        assert!(matches!(response, Ok(QueryResponse::Ok(3))));
+
+        db.set_primary_key(0).unwrap();
+        let response = db.append_row_returning_key(vec![Data::from("Eve"), Data::Int(2)]);
+        assert_eq!(response.unwrap().as_single(), Some(&Data::from("Eve")));
+
+        let response = db.append_row_returning_key(vec![Data::from("Eve"), Data::Int(3)]);
+        assert!(matches!(response, Err(QueryError::DuplicateKey)));
+    }
+
+    #[test]
+    fn concat_columns_treats_null_as_empty() {
+        let mut db = people();
+        let last = vec![Data::from("Lee"), Data::Null, Data::from("Moe")];
+        db.add_column_with_values("Last".to_string(), Type::String, last).unwrap();
+        db.concat_columns(0, 2, " ", "Full".to_string()).unwrap();
+
+        assert_eq!(db.transpose()[3], [
+            Data::from("Ann Lee"),
+            Data::from("Bob "),
+            Data::from("Cid Moe"),
+        ]);
+
+        let response = db.concat_columns(0, 1, " ", "Other".to_string());
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::String, Type::Int))));
+    }
+
+    fn full_names() -> Db {
+        let mut db = Db::default();
+        db.append_column("Full".to_string(), Type::String).unwrap();
+
+        for val in [Data::from("Ann Lee Moe"), Data::from("Bob"), Data::Null] {
+            db.append_row(vec![val]).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn split_column_splits_on_the_first_separator() {
+        let mut db = full_names();
+        let names = ("First".to_string(), "Last".to_string());
+
+        let response = db.split_column(0, " ", names, false).unwrap();
+        let QueryResponse::ModifiedColumns(ids) = response else {
+            panic!("expected the new columns");
+        };
+        assert!(matches!(ids.as_slice(), [Id::Index(0), Id::Index(1)]));
+        assert_eq!(db.header_line(','), "First,Last");
+        assert_eq!(db.rows(), [
+            vec![Data::from("Ann"), Data::from("Lee Moe")],
+            vec![Data::from("Bob"), Data::Null],
+            vec![Data::Null, Data::Null],
+        ]);
+    }
+
+    #[test]
+    fn split_column_can_keep_the_original() {
+        let mut db = full_names();
+        let names = ("First".to_string(), "Last".to_string());
+
+        let response = db.split_column(0, " ", names, true).unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedColumns(ids) if ids.len() == 2));
+        assert_eq!(db.header_line(','), "Full,First,Last");
+    }
+
+    #[test]
+    fn split_column_rejects_bad_input() {
+        let mut db = people();
+        let names = || ("A".to_string(), "B".to_string());
+
+        let response = db.split_column(1, " ", names(), false);
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::String, Type::Int))));
+
+        let response = db.split_column(0, " ", ("A".to_string(), "A".to_string()), false);
+        assert!(matches!(response, Err(QueryError::DuplicateName(_))));
+
+        let response = db.split_column(0, " ", ("Age".to_string(), "B".to_string()), false);
+        assert!(matches!(response, Err(QueryError::DuplicateName(_))));
+        assert!(db == people());
+    }
+
+    #[test]
+    fn row_hash_is_pinned() {
+        let db = Db::try_new(vec![Column::new("V".to_string(), Type::Int)], vec![vec![
+            Data::Int(1),
+        ]])
+        .unwrap();
+
+        assert_eq!(db.row_hash(0).unwrap(), 0x8c9f_692d_6ca6_50fd);
+    }
+
+    #[test]
+    fn row_hash_follows_the_values() {
+        let mut db = people();
+        let bob = db.row_hash(1).unwrap();
+        assert_ne!(db.row_hash(0).unwrap(), bob);
+
+        db.delete_row_by_index(0).unwrap();
+        assert_eq!(db.row_hash(0).unwrap(), bob);
+        assert!(matches!(db.row_hash(2), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn column_metadata_is_replaced_per_key() {
+        let mut db = people();
+        db.set_column_metadata(1, "unit".to_string(), "years".to_string()).unwrap();
+        db.set_column_metadata(1, "unit".to_string(), "months".to_string()).unwrap();
+        db.set_column_metadata(1, "source".to_string(), "form".to_string()).unwrap();
+
+        assert_eq!(db.column_metadata(1, "unit").unwrap(), Some("months"));
+        assert_eq!(db.column_metadata(0, "unit").unwrap(), None);
+        assert_eq!(db.columns()[1].metadata_entries().len(), 2);
+
+        let response = db.set_column_metadata(2, "unit".to_string(), "none".to_string());
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn column_metadata_is_persisted() {
+        let mut db = people();
+        db.set_column_metadata(1, "unit".to_string(), "years".to_string()).unwrap();
+
+        let loaded = Db::try_from(db.to_tora_bytes().unwrap().as_slice()).unwrap();
+        assert_eq!(loaded.column_metadata(1, "unit").unwrap(), Some("years"));
+        assert!(loaded == db);
+    }
+
+    #[test]
+    fn oversized_metadata_length_is_malformed() {
+        let column = [&[1, 0, 0, 0][..], b"A\0", &[0, 0, 5, 1], &u32::MAX.to_le_bytes()].concat();
+        assert_malformed(&with_header(&column));
+    }
+
+    #[test]
+    fn append_rows_transactional_rolls_back_on_failure() {
+        let mut db = people();
+        db.create_index(1).unwrap();
+        let events = record_events(&mut db);
+
+        let rows = vec![vec![Data::from("Dee"), Data::Int(1)], vec![Data::from("Eve")]];
+        let (i, e) = batch_error(db.append_rows_transactional(rows));
+        assert_eq!(i, 1);
+        assert!(matches!(e, QueryError::DataMismatch));
+
+        assert!(db == people());
+        assert!(db.lookup(1, &Data::Int(1)).unwrap().is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn append_rows_transactional_checks_keys_within_the_batch() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+
+        let rows = vec![
+            vec![Data::from("Dee"), Data::Int(1)],
+            vec![Data::from("Dee"), Data::Int(2)],
+        ];
+        let (i, e) = batch_error(db.append_rows_transactional(rows));
+        assert_eq!(i, 1);
+        assert!(matches!(e, QueryError::DuplicateKey));
+        assert_eq!(db.find_by_key(&Data::from("Dee")), None);
+    }
+
+    #[test]
+    fn append_rows_transactional_appends_every_row() {
+        let mut db = people();
+        let events = record_events(&mut db);
+        let rows = vec![vec![Data::from("Dee"), Data::Int(1)], vec![Data::from("Eve"), Data::Null]];
+
+        let response = db.append_rows_transactional(rows).unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedRows(rows) if rows == [3, 4]));
+        assert_eq!(db.rows().len(), 5);
+        assert_eq!(*events.lock().unwrap(), [
+            ChangeEvent::RowInserted(3),
+            ChangeEvent::RowInserted(4),
+        ]);
+    }
+
+    #[test]
+    fn query_ref_only_runs_reads() {
+        let db = people();
+        let response = db.query_ref(&Instruction::Fetch(0, 1)).unwrap();
+        assert_eq!(response.as_single(), Some(&Data::from("Bob")));
+
+        let response = db.query_ref(&Instruction::Fetch(0, 9));
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+        assert!(matches!(db.query_ref(&Instruction::Truncate), Err(QueryError::ReadOnly)));
+    }
+
+    #[test]
+    fn from_csv_infer_infers_column_types() {
+        let csv = concat!(
+            "id,name,score,big\r\n",
+            "1,\"Lee, Ann\",1.5,1\r\n",
+            "2,\"Bob \"\"B\"\"\",,9999999999\r\n",
+        );
+        let db = Db::from_csv_infer(csv.as_bytes()).unwrap();
+
+        let types: Vec<_> = db.columns().iter().map(Column::ty_restriction).collect();
+        assert_eq!(types, [&Type::Int, &Type::String, &Type::Double, &Type::Long]);
+        assert_eq!(db.rows(), [
+            vec![Data::Int(1), Data::from("Lee, Ann"), Data::Double(1.5), Data::Long(1)],
+            vec![Data::Int(2), Data::from("Bob \"B\""), Data::Null, Data::Long(9_999_999_999)],
+        ]);
+    }
+
+    #[test]
+    fn from_csv_infer_rejects_malformed_input() {
+        let (i, e) = batch_error(Db::from_csv_infer("a,b\n1,2\n3,4,5\n".as_bytes()));
+        assert_eq!(i, 1);
+        assert!(matches!(e, QueryError::DataMismatch));
+
+        let (i, _) = batch_error(Db::from_csv_infer("a,b\n1,2\n3\n".as_bytes()));
+        assert_eq!(i, 1);
+
+        let unterminated = Db::from_csv_infer("a\n\"open".as_bytes());
+        assert!(matches!(unterminated, Err(QueryError::DataMismatch)));
+
+        let repeated = Db::from_csv_infer("a,a\n".as_bytes());
+        assert!(matches!(repeated, Err(QueryError::DuplicateName(_))));
+        assert_eq!(Db::from_csv_infer("".as_bytes()).unwrap().expected_row_len(), 0);
+    }
+
+    #[test]
+    fn replace_all_rows_is_atomic() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+        db.create_index(1).unwrap();
+
+        let rows = vec![vec![Data::from("X"), Data::Int(1)], vec![Data::from("X"), Data::Int(2)]];
+        let (i, e) = batch_error(db.replace_all_rows(rows));
+        assert_eq!(i, 1);
+        assert!(matches!(e, QueryError::DuplicateKey));
+        assert_eq!(db.rows(), people().rows());
+        assert_eq!(db.lookup(1, &Data::Int(25)).unwrap(), [1]);
+
+        let rows = vec![vec![Data::from("X"), Data::Int(1)], vec![Data::from("Y"), Data::Int(25)]];
+        let response = db.replace_all_rows(rows).unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedRows(rows) if rows == [0, 1]));
+        assert_eq!(db.find_by_key(&Data::from("Y")), Some(1));
+        assert_eq!(db.lookup(1, &Data::Int(25)).unwrap(), [1]);
+    }
+
+    #[test]
+    fn to_sql_inserts_quotes_and_escapes() {
+        let mut db = Db::default();
+        db.append_column_required("Id".to_string(), Type::Int, Data::Int(0)).unwrap();
+        db.append_column_string("Na\"me".to_string(), Some(8)).unwrap();
+        db.append_column("Score".to_string(), Type::Double).unwrap();
+        db.append_row(vec![Data::Int(1), Data::from("O'Neil"), Data::Double(f64::NAN)]).unwrap();
+        db.append_row(vec![Data::Int(2), Data::Null, Data::Double(0.5)]).unwrap();
+        db.set_primary_key(0).unwrap();
+
+        assert_eq!(db.to_sql_inserts("t"), concat!(
+            "CREATE TABLE \"t\" (\"Id\" INTEGER NOT NULL PRIMARY KEY, \"Na\"\"me\" VARCHAR(8), ",
+            "\"Score\" DOUBLE PRECISION);\n",
+            "INSERT INTO \"t\" VALUES (1, 'O''Neil', NULL);\n",
+            "INSERT INTO \"t\" VALUES (2, NULL, 0.5);\n",
+        ));
+    }
+
+    #[test]
+    fn non_finite_values_are_written_as_null() {
+        assert_eq!(sql_literal(&Data::Float(f32::INFINITY)), "NULL");
+        assert_eq!(sql_literal(&Data::Double(f64::NEG_INFINITY)), "NULL");
+        assert_eq!(sql_literal(&Data::Double(-2.5)), "-2.5");
+    }
+
+    #[test]
+    fn column_is_unique_ignores_null() {
+        let mut db = people();
+        assert!(db.column_is_unique(0).unwrap());
+
+        db.append_row(vec![Data::from("Dee"), Data::Null]).unwrap();
+        assert!(db.column_is_unique(1).unwrap());
+
+        db.append_row(vec![Data::from("Ann"), Data::Int(1)]).unwrap();
+        assert!(!db.column_is_unique(0).unwrap());
+        assert!(matches!(db.column_is_unique(2), Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn fill_column_with_sequence_overwrites_every_row() {
+        let mut db = people();
+        db.append_column("Id".to_string(), Type::Int).unwrap();
+        db.fill_column_with_sequence(2, 10, 5).unwrap();
+        assert_eq!(db.transpose()[2], [Data::Int(10), Data::Int(15), Data::Int(20)]);
+
+        db.append_column("Big".to_string(), Type::Long).unwrap();
+        db.fill_column_with_sequence(3, i64::MAX, -1).unwrap();
+        assert_eq!(db.rows()[2][3], Data::Long(i64::MAX - 2));
+    }
+
+    #[test]
+    fn fill_column_with_sequence_fails_without_changes() {
+        let mut db = people();
+        db.append_column("Id".to_string(), Type::Int).unwrap();
+        db.fill_column_with_sequence(2, 0, 1).unwrap();
+
+        let response = db.fill_column_with_sequence(2, i32::MAX as i64, 1);
+        assert!(matches!(response, Err(QueryError::DataOutOfBounds)));
+        assert_eq!(db.rows()[2][2], Data::Int(2));
+
+        let response = db.fill_column_with_sequence(0, 0, 1);
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Long, Type::String))));
+
+        db.set_primary_key(2).unwrap();
+        let response = db.fill_column_with_sequence(2, 0, 0);
+        assert!(matches!(response, Err(QueryError::DuplicateKey)));
+    }
+
+    #[test]
+    fn backfill_values_are_checked_against_the_column() {
+        let mut db = people();
+
+        let response = db.append_column_default("A".to_string(), Type::Int, Data::from("x"));
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+
+        let response = db.append_column_required("A".to_string(), Type::Int, Data::Null);
+        assert!(matches!(response, Err(QueryError::NullConstraintViolation)));
+
+        let mut col = Column::new_string("A".to_string(), Some(1));
+        col.default = Data::from("long");
+        assert!(matches!(db.append_column_def(col), Err(QueryError::LengthExceeded)));
+        assert!(db == people());
+    }
+
+    #[test]
+    fn transpose_returns_each_column() {
+        let columns = people().transpose();
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns[1], [Data::Int(31), Data::Int(25), Data::Null]);
+
+        let mut empty = people();
+        empty.truncate().unwrap();
+        assert_eq!(empty.transpose(), [Vec::new(), Vec::new()]);
+    }
+
+    #[test]
+    fn undo_restores_a_dropped_column() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+        db.create_index(0).unwrap();
+
+        let token = db.delete_column_undoable(0).unwrap();
+        assert_eq!(db.header_line(','), "Age");
+        assert_eq!(db.primary_key(), None);
+
+        db.undo(token).unwrap();
+        assert_eq!(db.rows(), people().rows());
+        assert_eq!(db.primary_key(), Some(0));
+        assert_eq!(db.lookup(0, &Data::from("Bob")).unwrap(), [1]);
+    }
+
+    #[test]
+    fn undo_fails_once_the_rows_changed() {
+        let mut db = people();
+        let token = db.delete_column_undoable(1).unwrap();
+        db.append_row(vec![Data::from("Dee")]).unwrap();
+
+        assert!(matches!(db.undo(token), Err(QueryError::StaleUndo)));
+        assert_eq!(db.header_line(','), "Name");
+    }
+
+    #[test]
+    fn undo_fails_once_the_rows_moved() {
+        let mut db = people();
+        let token = db.delete_column_undoable(1).unwrap();
+        db.sort_by_columns(&[(0, false)]).unwrap();
+
+        assert!(matches!(db.undo(token), Err(QueryError::StaleUndo)));
+        assert_eq!(db.header_line(','), "Name");
+        assert_eq!(db.rows()[0], [Data::from("Cid")]);
+    }
+
+    #[test]
+    fn undo_fails_once_the_columns_moved() {
+        let mut db = people();
+        let token = db.rename_column_undoable(1, "Years".to_string()).unwrap();
+        db.swap_columns(0, 1).unwrap();
+
+        assert!(matches!(db.undo(token), Err(QueryError::StaleUndo)));
+        assert_eq!(db.header_line(','), "Years,Name");
+    }
+
+    #[test]
+    fn undo_restores_a_previous_name() {
+        let mut db = people();
+        let token = db.rename_column_undoable(1, "Years".to_string()).unwrap();
+        assert_eq!(db.header_line(','), "Name,Years");
+
+        db.undo(token).unwrap();
+        assert_eq!(db.header_line(','), "Name,Age");
+
+        let token = db.rename_column_undoable(1, "Years".to_string()).unwrap();
+        db.rename_columns(vec![(0, "Age".to_string())]).unwrap();
+        assert!(matches!(db.undo(token), Err(QueryError::StaleUndo)));
+    }
+
+    #[test]
+    fn validate_row_runs_the_insert_checks() {
+        let mut db = people();
+        db.set_primary_key(0).unwrap();
+
+        assert!(db.validate_row(&vec![Data::from("Dee"), Data::Int(1)]).is_ok());
+
+        let taken = vec![Data::from("Ann"), Data::Int(1)];
+        assert!(matches!(db.validate_row(&taken), Err(QueryError::DuplicateKey)));
+
+        let short = vec![Data::from("Dee")];
+        assert!(matches!(db.validate_row(&short), Err(QueryError::DataMismatch)));
+        assert_eq!(db.rows().len(), 3);
+    }
+
+    #[test]
+    fn append_default_row_uses_every_default() {
+        let mut db = people();
+        db.set_column_default(1, Data::Int(18)).unwrap();
+        db.append_default_row().unwrap();
+        assert_eq!(db.rows()[3], [Data::Null, Data::Int(18)]);
+
+        let mut keyed = Db::default();
+        keyed.append_column_required("Id".to_string(), Type::Int, Data::Int(0)).unwrap();
+        keyed.set_primary_key(0).unwrap();
+        keyed.append_default_row().unwrap();
+        assert!(matches!(keyed.append_default_row(), Err(QueryError::DuplicateKey)));
+    }
+
+    #[test]
+    fn optimize_types_narrows_lossless_columns() {
+        let mut db = Db::default();
+        let defs = [
+            ("Small", Type::Long),
+            ("Big", Type::Long),
+            ("Half", Type::Double),
+            ("Empty", Type::Double),
+        ];
+        db.append_columns(defs.map(|(name, ty)| (name.to_string(), ty)).to_vec()).unwrap();
+        let rows = vec![
+            vec![Data::Long(1), Data::Long(1 << 40), Data::Double(0.5), Data::Null],
+            vec![Data::Null, Data::Long(2), Data::Double(0.1), Data::Null],
+        ];
+        for row in rows {
+            db.append_row(row).unwrap();
+        }
+        db.set_column_default(2, Data::Double(0.25)).unwrap();
+
+        let response = db.optimize_types().unwrap();
+        let QueryResponse::ModifiedColumns(ids) = response else {
+            panic!("expected the narrowed columns");
+        };
+        assert!(matches!(ids.as_slice(), [Id::Index(0)]));
+
+        let types: Vec<_> = db.columns().iter().map(Column::ty_restriction).collect();
+        assert_eq!(types, [&Type::Int, &Type::Long, &Type::Double, &Type::Double]);
+        assert_eq!(db.rows()[0][0], Data::Int(1));
+
+        db.delete_row_by_index(1).unwrap();
+        let response = db.optimize_types().unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedColumns(ids) if ids.len() == 1));
+        assert_eq!(db.rows()[0][2], Data::Float(0.5));
+        assert_eq!(db.columns()[2].default(), &Data::Float(0.25));
+    }
+
+    #[test]
+    fn optimized_databases_can_be_rebuilt() {
+        let mut db = Db::default();
+        db.append_column_required("Score".to_string(), Type::Long, Data::Long(5)).unwrap();
+        db.append_column("Big".to_string(), Type::Long).unwrap();
+        db.set_column_default(1, Data::Long(1 << 40)).unwrap();
+        db.append_row(vec![Data::Long(1), Data::Long(2)]).unwrap();
+
+        let response = db.optimize_types().unwrap();
+        assert!(matches!(response, QueryResponse::ModifiedColumns(ids) if ids.len() == 1));
+        assert_eq!(db.columns()[0].default(), &Data::Int(5));
+        assert_eq!(db.columns()[1].ty_restriction(), &Type::Long);
+
+        db.append_default_row().unwrap();
+        let mut rebuilt = Db::default();
+        rebuilt.apply_all(db.export_instructions()).unwrap();
+        assert!(rebuilt == db);
+    }
+
+    #[test]
+    fn non_finite_values_can_be_rejected() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::Double).unwrap();
+        db.append_row(vec![Data::Double(f64::NAN)]).unwrap();
+        db.set_reject_non_finite(true);
+
+        let row = vec![Data::Double(f64::INFINITY)];
+        assert!(matches!(db.append_row(row), Err(QueryError::NonFinite)));
+
+        let response = db.set_column_default(0, Data::Double(f64::NAN));
+        assert!(matches!(response, Err(QueryError::NonFinite)));
+
+        let inf = Data::Float(f32::NEG_INFINITY);
+        let response = db.append_column_default("W".to_string(), Type::Float, inf);
+        assert!(matches!(response, Err(QueryError::NonFinite)));
+
+        let values = vec![Data::Double(f64::NAN)];
+        let response = db.add_column_with_values("W".to_string(), Type::Double, values);
+        assert!(matches!(response, Err(QueryError::NonFinite)));
+        assert_eq!(db.expected_row_len(), 1);
+
+        db.append_row(vec![Data::Double(1.0)]).unwrap();
+        assert_eq!(db.rows().len(), 2);
+    }
+
+    #[test]
+    fn casts_to_non_finite_values_fail() {
+        let mut db = Db::default();
+        db.append_column("V".to_string(), Type::String).unwrap();
+        db.append_row(vec![Data::from("inf")]).unwrap();
+        db.set_reject_non_finite(true);
+
+        let report = db.try_cast_column(0, Type::Double).unwrap();
+        assert_eq!(report.failed(), [(0, Data::from("inf"))]);
+        assert_eq!(db.columns()[0].ty_restriction(), &Type::String);
+    }
+
+    #[test]
+    fn select_where_all_requires_every_condition() {
+        let db = people();
+        let conditions = vec![
+            (1, Comparison::Gt, Data::Int(20)),
+            (0, Comparison::Ne, Data::from("Ann")),
+        ];
+        assert_eq!(db.select_where_all(conditions).unwrap(), [db.rows()[1].clone()]);
+
+        let under = vec![(1, Comparison::Lt, Data::Int(100))];
+        assert_eq!(db.select_where_all(under).unwrap().len(), 2);
+        assert_eq!(db.select_where_all(Vec::new()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn select_where_all_validates_conditions_first() {
+        let db = people();
+
+        let mistyped = vec![(1, Comparison::Eq, Data::from("x"))];
+        let response = db.select_where_all(mistyped);
+        assert!(matches!(response, Err(QueryError::TypeMismatch(Type::Int, Type::String))));
+
+        let missing = vec![(2, Comparison::Eq, Data::Int(1))];
+        let response = db.select_where_all(missing);
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn select_where_any_requires_one_condition() {
+        let db = people();
+        let conditions = vec![
+            (1, Comparison::Gt, Data::Int(30)),
+            (0, Comparison::Eq, Data::from("Cid")),
+        ];
+        let rows = db.select_where_any(conditions).unwrap();
+        assert_eq!(rows, [db.rows()[0].clone(), db.rows()[2].clone()]);
+        assert_eq!(db.select_where_any(Vec::new()).unwrap(), db.rows());
+
+        let invalid = vec![(0, Comparison::Eq, Data::from("Ann")), (2, Comparison::Eq, Data::Null)];
+        let response = db.select_where_any(invalid);
+        assert!(matches!(response, Err(QueryError::IndexOutOfBounds)));
+    }
+
+    #[test]
+    fn apply_all_best_effort_continues_past_failures() {
+        let mut db = people();
+        let results = db.apply_all_best_effort(vec![
+            Instruction::AppendRow(vec![Data::from("Dee")]),
+            Instruction::AppendRow(vec![Data::from("Dee"), Data::Int(1)]),
+            Instruction::DeleteColumn(Id::Name("Height".to_string())),
+            Instruction::Fetch(0, 3),
+        ]);
+
+        assert!(matches!(results.as_slice(), [
+            Err(QueryError::DataMismatch),
+            Ok(QueryResponse::Ok(3)),
+            Err(QueryError::NotFound),
+            Ok(QueryResponse::OkSingle(_)),
+        ]));
+        assert_eq!(db.rows().len(), 4);
     }
 }
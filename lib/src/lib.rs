@@ -1,6 +1,12 @@
-use derive_more::Display;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use derive_more::{Display, Error};
 use tora::{ReadEnum, ReadStruct, WriteEnum, WriteStruct};
 
+use crate::engine::QueryError;
+
 pub mod engine;
 
 /// An index of a row or column.
@@ -15,7 +21,7 @@ pub enum Id {
     Index(Index),
 }
 
-#[derive(Display, ReadEnum, WriteEnum)]
+#[derive(Display, Debug, ReadEnum, WriteEnum)]
 pub enum Instruction {
     #[display("DELETE_COL @{_0}")]
     DeleteColumn(Id),
@@ -31,9 +37,71 @@ pub enum Instruction {
 
     #[display("FETCH @({_0}) FROM @({_1})")]
     Fetch(Index, Index),
+
+    #[display("CREATE_INDEX @{_0}")]
+    CreateIndex(Id),
+
+    #[display("DROP_INDEX @{_0}")]
+    DropIndex(Id),
+
+    #[display("TRUNCATE")]
+    Truncate,
+
+    #[display("CLEAR")]
+    Clear,
+
+    #[display("APPEND_COL {_0}")]
+    AppendColumnDef(Column),
+
+    #[display("SET_PRIMARY_KEY @{_0}")]
+    SetPrimaryKey(Id),
 }
 
-#[derive(Display, Debug, PartialEq, ReadEnum, WriteEnum, Clone)]
+/// A comparison between two values, used to filter rows.
+#[derive(Display, Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    #[display("=")]
+    Eq,
+
+    #[display("!=")]
+    Ne,
+
+    #[display("<")]
+    Lt,
+
+    #[display("<=")]
+    Le,
+
+    #[display(">")]
+    Gt,
+
+    #[display(">=")]
+    Ge,
+}
+
+impl Comparison {
+    /// Compares the values using SQL's three-valued logic.
+    ///
+    /// Returns [None] if either value is `NULL`. Values of the same type are compared as by
+    /// [Data::total_cmp].
+    pub fn evaluate(&self, left: &Data, right: &Data) -> Option<bool> {
+        if *left == Data::Null || *right == Data::Null {
+            return None;
+        }
+        let ordering = left.total_cmp(right);
+
+        Some(match self {
+            Self::Eq => ordering.is_eq(),
+            Self::Ne => ordering.is_ne(),
+            Self::Lt => ordering.is_lt(),
+            Self::Le => ordering.is_le(),
+            Self::Gt => ordering.is_gt(),
+            Self::Ge => ordering.is_ge(),
+        })
+    }
+}
+
+#[derive(Display, Debug, PartialEq, Hash, ReadEnum, WriteEnum, Clone)]
 pub enum Type {
     Int,
     Long,
@@ -42,6 +110,29 @@ pub enum Type {
     String,
 }
 
+impl FromStr for Type {
+    type Err = ParseTypeError;
+
+    /// Parses a type from its name, ignoring case.
+    ///
+    /// `str` is accepted as an alias of `string`, matching the suffix used by [Data]'s display.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "int" => Ok(Self::Int),
+            "long" => Ok(Self::Long),
+            "float" => Ok(Self::Float),
+            "double" => Ok(Self::Double),
+            "string" | "str" => Ok(Self::String),
+            _ => Err(ParseTypeError(s.to_string())),
+        }
+    }
+}
+
+/// An error produced when a string does not name a [Type].
+#[derive(Display, Debug, PartialEq, Error)]
+#[display("unknown type `{_0}`")]
+pub struct ParseTypeError(#[error(not(source))] String);
+
 #[derive(Display, Debug, PartialEq, ReadEnum, WriteEnum, Clone)]
 pub enum Data {
     #[display("{_0}int")]
@@ -74,13 +165,197 @@ impl Data {
             Self::Null => Type::String,
         }
     }
+
+    /// Compares this value against another, producing a total ordering.
+    ///
+    /// `NULL` is ordered before every other value, and values of different types are ordered by
+    /// their type. Floating point values are ordered as by [f64::total_cmp].
+    pub fn total_cmp(&self, other: &Data) -> Ordering {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a.cmp(b),
+            (Self::Long(a), Self::Long(b)) => a.cmp(b),
+            (Self::Float(a), Self::Float(b)) => a.total_cmp(b),
+            (Self::Double(a), Self::Double(b)) => a.total_cmp(b),
+            (Self::String(a), Self::String(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+
+    /// Compares this value against another using SQL's three-valued logic.
+    ///
+    /// Returns [None] if either value is `NULL`, as the result is unknown. Otherwise, values are
+    /// equal only if they have the same type and value.
+    pub fn null_safe_eq(&self, other: &Data) -> Option<bool> {
+        match (self, other) {
+            (Self::Null, _) | (_, Self::Null) => None,
+            _ => Some(self == other),
+        }
+    }
+
+    /// Returns the position of this value's variant in the ordering of [Data::total_cmp].
+    const fn rank(&self) -> u8 {
+        match self {
+            Self::Null => 0,
+            Self::Int(_) => 1,
+            Self::Long(_) => 2,
+            Self::Float(_) => 3,
+            Self::Double(_) => 4,
+            Self::String(_) => 5,
+        }
+    }
+
+    /// Converts this value into the given type, if the conversion is lossless.
+    ///
+    /// Strings are parsed into numeric types, failing if they are out of range, and all values can
+    /// be converted into strings.
+    /// `NULL` converts into `NULL` regardless of the target type.
+    pub fn coerce_to(&self, ty: &Type) -> Option<Data> {
+        Some(match (self, ty) {
+            (Self::Null, _) => Self::Null,
+            (Self::String(s), _) => match ty {
+                Type::Int => Self::Int(s.trim().parse().ok()?),
+                Type::Long => Self::Long(s.trim().parse().ok()?),
+                Type::Float => Self::Float(parse_float(s.trim())?),
+                Type::Double => Self::Double(parse_float(s.trim())?),
+                Type::String => self.clone(),
+            },
+            (_, Type::String) => Self::String(self.to_plain_string()),
+            (Self::Int(v), Type::Int) => Self::Int(*v),
+            (Self::Int(v), Type::Long) => Self::Long(*v as i64),
+            (Self::Int(v), Type::Float) => {
+                Self::Float(exact(*v as f32, |f| float_to_i32(f as f64) == Some(*v))?)
+            }
+            (Self::Int(v), Type::Double) => Self::Double(*v as f64),
+            (Self::Long(v), Type::Int) => Self::Int(i32::try_from(*v).ok()?),
+            (Self::Long(v), Type::Long) => Self::Long(*v),
+            (Self::Long(v), Type::Float) => {
+                Self::Float(exact(*v as f32, |f| float_to_i64(f as f64) == Some(*v))?)
+            }
+            (Self::Long(v), Type::Double) => {
+                Self::Double(exact(*v as f64, |f| float_to_i64(f) == Some(*v))?)
+            }
+            (Self::Float(v), Type::Int) => Self::Int(float_to_i32(*v as f64)?),
+            (Self::Float(v), Type::Long) => Self::Long(float_to_i64(*v as f64)?),
+            (Self::Float(v), Type::Float) => Self::Float(*v),
+            (Self::Float(v), Type::Double) => Self::Double(*v as f64),
+            (Self::Double(v), Type::Int) => Self::Int(float_to_i32(*v)?),
+            (Self::Double(v), Type::Long) => Self::Long(float_to_i64(*v)?),
+            (Self::Double(v), Type::Float) => Self::Float(exact(*v as f32, |f| f as f64 == *v)?),
+            (Self::Double(v), Type::Double) => Self::Double(*v),
+        })
+    }
+
+    /// Returns this value as a string without any type annotations.
+    pub(crate) fn to_plain_string(&self) -> String {
+        match self {
+            Self::Int(v) => v.to_string(),
+            Self::Long(v) => v.to_string(),
+            Self::Float(v) => v.to_string(),
+            Self::Double(v) => v.to_string(),
+            Self::String(v) => v.clone(),
+            Self::Null => "NULL".to_string(),
+        }
+    }
+}
+
+macro_rules! try_from_data {
+    ($($variant:ident($t:ty)),*) => {
+        $(
+        impl TryFrom<&Data> for $t {
+            type Error = QueryError;
+
+            /// Returns an [Err] if the data is not of the matching variant.
+            fn try_from(value: &Data) -> Result<Self, Self::Error> {
+                match value {
+                    Data::$variant(v) => Ok(v.clone()),
+                    _ => Err(QueryError::TypeMismatch(Type::$variant, value.get_type())),
+                }
+            }
+        }
+        )*
+    };
+}
+
+try_from_data!(Int(i32), Long(i64), Float(f32), Double(f64), String(String));
+
+macro_rules! data_from {
+    ($($variant:ident($t:ty)),*) => {
+        $(
+        impl From<$t> for Data {
+            fn from(value: $t) -> Self {
+                Self::$variant(value)
+            }
+        }
+        )*
+    };
+}
+
+data_from!(Int(i32), Long(i64), Float(f32), Double(f64), String(String));
+
+impl From<&str> for Data {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+/// Returns the converted value if it converts back into the original value.
+fn exact<T, F>(converted: T, round_trips: F) -> Option<T>
+where
+    T: Copy,
+    F: FnOnce(T) -> bool,
+{
+    round_trips(converted).then_some(converted)
+}
+
+/// Parses the text as a float, rejecting values too large to be represented.
+///
+/// Such values would otherwise parse as infinite, while text spelling out infinity or `NaN` is
+/// still accepted.
+fn parse_float<T>(s: &str) -> Option<T>
+where T: FromStr + Into<f64> + Copy {
+    let v: T = s.parse().ok()?;
+    let literal = s.trim_start_matches(['+', '-']).to_ascii_lowercase();
+
+    let special = matches!(literal.as_str(), "inf" | "infinity" | "nan");
+    (v.into().is_finite() || special).then_some(v)
+}
+
+/// Converts the float into an [i32] if it is a whole number within range.
+fn float_to_i32(f: f64) -> Option<i32> {
+    (f.fract() == 0.0 && f >= i32::MIN as f64 && f <= i32::MAX as f64).then_some(f as i32)
+}
+
+/// Converts the float into an [i64] if it is a whole number within range.
+fn float_to_i64(f: f64) -> Option<i64> {
+    (f.fract() == 0.0 && f >= i64::MIN as f64 && f < -(i64::MIN as f64)).then_some(f as i64)
+}
+
+impl Hash for Data {
+    /// Hashes floating point values by their bit pattern, treating `0.0` and `-0.0` as equal.
+    ///
+    /// Each variant is prefixed with a fixed tag byte, so the hash only depends on the hasher.
+    fn hash<H>(&self, state: &mut H)
+    where H: Hasher {
+        match self {
+            Self::Int(v) => (0u8, v).hash(state),
+            Self::Long(v) => (1u8, v).hash(state),
+            Self::Float(v) => (2u8, if *v == 0.0 { 0 } else { v.to_bits() }).hash(state),
+            Self::Double(v) => (3u8, if *v == 0.0 { 0 } else { v.to_bits() }).hash(state),
+            Self::String(v) => (4u8, v).hash(state),
+            Self::Null => 5u8.hash(state),
+        }
+    }
 }
 
-#[derive(Display, WriteStruct, ReadStruct)]
+#[derive(Display, Debug, PartialEq, Hash, WriteStruct, ReadStruct, Clone)]
 #[display("[`{name}`|{ty_restriction}]")]
 pub struct Column {
     name: String,
     ty_restriction: Type,
+    max_len: Option<u32>,
+    default: Data,
+    nullable: bool,
+    metadata: Vec<(String, String)>,
 }
 
 impl Column {
@@ -92,10 +367,159 @@ impl Column {
         &self.ty_restriction
     }
 
+    /// The maximum length in bytes of the string values in this column, if any.
+    pub const fn max_len(&self) -> Option<u32> {
+        self.max_len
+    }
+
+    /// The value used to fill this column when a row omits it.
+    pub const fn default(&self) -> &Data {
+        &self.default
+    }
+
+    /// Returns true if this column accepts `NULL` values.
+    pub const fn is_nullable(&self) -> bool {
+        self.nullable
+    }
+
+    /// Returns the metadata value stored under the given key, if any.
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns every metadata entry of this column, in order of insertion.
+    pub fn metadata_entries(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+
+    /// Stores the metadata value under the given key, replacing any previous value.
+    pub(crate) fn set_metadata(&mut self, key: String, value: String) {
+        match self.metadata.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.metadata.push((key, value)),
+        }
+    }
+
     pub const fn new(name: String, ty_restriction: Type) -> Self {
         Self {
             name,
             ty_restriction,
+            max_len: None,
+            default: Data::Null,
+            nullable: true,
+            metadata: Vec::new(),
+        }
+    }
+
+    /// Constructs a new string column, limiting the length in bytes of its values.
+    pub const fn new_string(name: String, max_len: Option<u32>) -> Self {
+        Self {
+            name,
+            ty_restriction: Type::String,
+            max_len,
+            default: Data::Null,
+            nullable: true,
+            metadata: Vec::new(),
+        }
+    }
+
+    /// Constructs a new column which rejects `NULL` values, filled with the given default when a
+    /// row omits it.
+    pub const fn new_required(name: String, ty_restriction: Type, default: Data) -> Self {
+        Self {
+            name,
+            ty_restriction,
+            max_len: None,
+            default,
+            nullable: false,
+            metadata: Vec::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_converts_from_primitives() {
+        assert_eq!(Data::from(1i32), Data::Int(1));
+        assert_eq!(Data::from(1i64), Data::Long(1));
+        assert_eq!(Data::from(1.5f32), Data::Float(1.5));
+        assert_eq!(Data::from(1.5f64), Data::Double(1.5));
+        assert_eq!(Data::from("a"), Data::String("a".to_string()));
+        assert_eq!(Data::from("a".to_string()), Data::String("a".to_string()));
+    }
+
+    #[test]
+    fn primitives_convert_from_matching_data() {
+        assert_eq!(i32::try_from(&Data::Int(3)).unwrap(), 3);
+        assert_eq!(String::try_from(&Data::from("a")).unwrap(), "a");
+
+        let mismatch = i64::try_from(&Data::Int(3));
+        assert!(matches!(mismatch, Err(QueryError::TypeMismatch(Type::Long, Type::Int))));
+    }
+
+    #[test]
+    fn type_parses_ignoring_case() {
+        assert_eq!("INT".parse::<Type>(), Ok(Type::Int));
+        assert_eq!("Double".parse::<Type>(), Ok(Type::Double));
+        assert_eq!("str".parse::<Type>(), Ok(Type::String));
+        assert_eq!("string".parse::<Type>(), Ok(Type::String));
+    }
+
+    #[test]
+    fn unknown_types_are_reported() {
+        let err = "bool".parse::<Type>().unwrap_err();
+        assert_eq!(err, ParseTypeError("bool".to_string()));
+        assert_eq!(err.to_string(), "unknown type `bool`");
+    }
+
+    #[test]
+    fn string_coercion_rejects_out_of_range_floats() {
+        assert_eq!(Data::from("1e39").coerce_to(&Type::Float), None);
+        assert_eq!(Data::from("1e309").coerce_to(&Type::Double), None);
+        assert_eq!(Data::from(" 2.5 ").coerce_to(&Type::Double), Some(Data::Double(2.5)));
+    }
+
+    #[test]
+    fn string_coercion_accepts_spelled_out_special_values() {
+        let inf = Data::from("-Infinity").coerce_to(&Type::Float);
+        assert_eq!(inf, Some(Data::Float(f32::NEG_INFINITY)));
+
+        let nan = Data::from("NaN").coerce_to(&Type::Double);
+        assert!(matches!(nan, Some(Data::Double(v)) if v.is_nan()));
+    }
+
+    #[test]
+    fn numeric_coercion_is_lossless() {
+        assert_eq!(Data::Long(1 << 40).coerce_to(&Type::Int), None);
+        assert_eq!(Data::Long(7).coerce_to(&Type::Int), Some(Data::Int(7)));
+        assert_eq!(Data::Double(0.1).coerce_to(&Type::Float), None);
+        assert_eq!(Data::Double(2.5).coerce_to(&Type::Long), None);
+        assert_eq!(Data::Int(16_777_217).coerce_to(&Type::Float), None);
+        assert_eq!(Data::Null.coerce_to(&Type::Int), Some(Data::Null));
+    }
+
+    #[test]
+    fn null_safe_eq_is_unknown_for_null() {
+        assert_eq!(Data::Int(1).null_safe_eq(&Data::Int(1)), Some(true));
+        assert_eq!(Data::Int(1).null_safe_eq(&Data::Long(1)), Some(false));
+        assert_eq!(Data::Int(1).null_safe_eq(&Data::Null), None);
+        assert_eq!(Data::Null.null_safe_eq(&Data::Null), None);
+        assert_eq!(Data::Null, Data::Null);
+    }
+
+    #[test]
+    fn comparisons_use_three_valued_logic() {
+        assert_eq!(Comparison::Lt.evaluate(&Data::Int(1), &Data::Int(2)), Some(true));
+        assert_eq!(Comparison::Ge.evaluate(&Data::from("b"), &Data::from("a")), Some(true));
+        assert_eq!(Comparison::Eq.evaluate(&Data::Double(0.5), &Data::Double(0.5)), Some(true));
+        assert_eq!(Comparison::Ne.evaluate(&Data::Int(1), &Data::Int(1)), Some(false));
+        assert_eq!(Comparison::Eq.evaluate(&Data::Null, &Data::Null), None);
+        assert_eq!(Comparison::Le.to_string(), "<=");
+    }
+}